@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use error::{Error, ErrorType};
+use query::{Client, Result};
+use ratelimit::RateLimiter;
+use values::*;
+
+/// Named sub-account with its own rate limit budget
+pub struct Account {
+    name: String,
+    username: String,
+    client: Client,
+    limiter: RateLimiter,
+}
+
+impl Account {
+    /// name: Name used to refer to the account within the set (required)
+    /// username: TradeSatoshi username, used as destination of transfers (required)
+    pub fn new(name: &str, username: &str, client: Client) -> Self {
+        Account {
+            name: name.to_string(),
+            username: username.to_string(),
+            client,
+            limiter: RateLimiter::new(1, Duration::from_millis(200)),
+        }
+    }
+
+    /// Limit requests made through the set for this account (default: 5 per second)
+    pub fn rate_limit(mut self, requests: u32, per: Duration) -> Self {
+        self.limiter = RateLimiter::new(requests, per);
+        self
+    }
+
+    /// Use a configured rate limiter instead of `rate_limit`, e.g. one reporting metrics
+    pub fn limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Run a call once the account's rate limit allows it
+    pub fn call<T, F>(&self, call: F) -> Result<T>
+    where
+        F: FnOnce(&Client) -> Result<T>,
+    {
+        self.limiter.wait();
+        call(&self.client)
+    }
+}
+
+/// Result of a call made on every account, in the order the accounts were added
+pub type PerAccount<T> = Vec<(String, Result<T>)>;
+
+/// Several sub-accounts queried concurrently
+pub struct AccountSet {
+    accounts: Vec<Account>,
+}
+
+impl AccountSet {
+    pub fn new() -> Self {
+        AccountSet {
+            accounts: Vec::new(),
+        }
+    }
+
+    /// Add an account, replacing any account with the same name
+    pub fn account(mut self, account: Account) -> Self {
+        self.accounts
+            .retain(|existing| existing.name != account.name);
+        self.accounts.push(account);
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.accounts.iter().map(|account| account.name()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.name == name)
+    }
+
+    /// Run a call on every account concurrently, each within its own rate limit
+    pub fn call_all<T, F>(&self, call: F) -> PerAccount<T>
+    where
+        T: Send,
+        F: Fn(&Client) -> Result<T> + Sync,
+    {
+        let call = &call;
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .accounts
+                .iter()
+                .map(|account| (account, scope.spawn(move || account.call(call))))
+                .collect();
+            handles
+                .into_iter()
+                .map(|(account, handle)| {
+                    let result = handle.join().unwrap_or_else(|_| {
+                        Err(Error {
+                            error_type: ErrorType::Panicked,
+                            message: format!("Call on account {} panicked", account.name),
+                        })
+                    });
+                    (account.name.clone(), result)
+                })
+                .collect()
+        })
+    }
+
+    /// Balances of every account
+    pub fn get_balances(&self) -> PerAccount<Vec<Balance>> {
+        self.call_all(|client| client.get_balances())
+    }
+
+    /// Open orders of every account
+    ///
+    /// market: The market name e.g. 'LTC_BTC' (optional, default: 'all')
+    /// count: The maximum count of records to return per account (optional, default: 20)
+    pub fn get_orders(&self, market: Option<String>, count: Option<u32>) -> PerAccount<Vec<Order>> {
+        self.call_all(|client| client.get_orders(market.clone(), count))
+    }
+
+    /// Balances summed per currency over all accounts, sorted by currency
+    ///
+    /// Fails if any account could not be queried, as the totals would be short.
+    pub fn aggregate_balances(&self) -> Result<Vec<Balance>> {
+        let mut totals: HashMap<String, Balance> = HashMap::new();
+        for (name, balances) in self.get_balances() {
+            let balances = balances.map_err(|error| Error {
+                error_type: error.error_type,
+                message: format!("Account {}: {}", name, error.message),
+            })?;
+            for balance in balances {
+                let total = totals
+                    .entry(balance.currency.clone())
+                    .or_insert_with(|| Balance {
+                        currency: balance.currency.clone(),
+                        currency_long: balance.currency_long.clone(),
+                        available: 0.0,
+                        total: 0.0,
+                        held_for_trades: 0.0,
+                        unconfirmed: 0.0,
+                        pending_withdraw: 0.0,
+                        address: None,
+                    });
+                total.available += balance.available;
+                total.total += balance.total;
+                total.held_for_trades += balance.held_for_trades;
+                total.unconfirmed += balance.unconfirmed;
+                total.pending_withdraw += balance.pending_withdraw;
+            }
+        }
+        let mut totals: Vec<Balance> = totals.drain().map(|(_, total)| total).collect();
+        totals.sort_by(|a, b| a.currency.cmp(&b.currency));
+        Ok(totals)
+    }
+
+    /// Transfer funds between two accounts of the set
+    ///
+    /// from: Name of the sending account (required)
+    /// to: Name of the receiving account (required)
+    /// currency: The currency name e.g. 'BTC' (required)
+    /// amount: The amount of coin to transfer e.g. 251.00000000 (required)
+    pub fn submit_transfer(
+        &self,
+        from: &str,
+        to: &str,
+        currency: String,
+        amount: f32,
+    ) -> Result<SubmitTransfer> {
+        if from == to {
+            return Err(Error {
+                error_type: ErrorType::ValidationError,
+                message: format!("Cannot transfer from account {} to itself", from),
+            });
+        }
+        let sender = self.find(from)?;
+        let receiver = self.find(to)?;
+        sender.call(|client| client.submit_transfer(currency, receiver.username.clone(), amount))
+    }
+
+    fn find(&self, name: &str) -> Result<&Account> {
+        self.get(name).ok_or_else(|| Error {
+            error_type: ErrorType::ValidationError,
+            message: format!("Unknown account: {}", name),
+        })
+    }
+}
+
+impl Default for AccountSet {
+    fn default() -> Self {
+        AccountSet::new()
+    }
+}
@@ -0,0 +1,347 @@
+use sha2::{Digest, Sha256};
+use tiny_keccak::keccak256;
+
+use tracing;
+
+use std::fmt;
+
+use query::MASK;
+use values::*;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+/// Address formats accepted for a currency
+struct CoinFormat {
+    /// Base58Check version bytes
+    base58: &'static [u8],
+    /// Human readable part of bech32 segwit addresses
+    bech32: Option<&'static str>,
+    /// Hex addresses with optional mixed case checksum
+    hex: bool,
+}
+
+fn coin_format(currency: &str) -> Option<CoinFormat> {
+    let format = match currency.to_uppercase().as_str() {
+        "BTC" => CoinFormat {
+            base58: &[0x00, 0x05],
+            bech32: Some("bc"),
+            hex: false,
+        },
+        "LTC" => CoinFormat {
+            base58: &[0x30, 0x32, 0x05],
+            bech32: Some("ltc"),
+            hex: false,
+        },
+        "DOGE" => CoinFormat {
+            base58: &[0x1e, 0x16],
+            bech32: None,
+            hex: false,
+        },
+        "DASH" => CoinFormat {
+            base58: &[0x4c, 0x10],
+            bech32: None,
+            hex: false,
+        },
+        "ETH" | "ETC" => CoinFormat {
+            base58: &[],
+            bech32: None,
+            hex: true,
+        },
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Outcome of a successful address check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressCheck {
+    Valid,
+    /// The currency has no known address format, the address was not checked
+    Unchecked,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressError {
+    /// The address matches none of the formats of the currency
+    InvalidFormat { currency: String, address: String },
+    BadChecksum { currency: String, address: String },
+    UnknownVersion { currency: String, version: u8 },
+    WrongNetwork { currency: String, prefix: String },
+}
+
+impl AddressError {
+    /// Message of the error with the address masked, for logs
+    pub fn masked(&self) -> String {
+        match *self {
+            AddressError::InvalidFormat { ref currency, .. } => AddressError::InvalidFormat {
+                currency: currency.clone(),
+                address: MASK.to_string(),
+            }
+            .to_string(),
+            AddressError::BadChecksum { ref currency, .. } => AddressError::BadChecksum {
+                currency: currency.clone(),
+                address: MASK.to_string(),
+            }
+            .to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressError::InvalidFormat {
+                ref currency,
+                ref address,
+            } => write!(f, "{} is not a valid {} address", address, currency),
+            AddressError::BadChecksum {
+                ref currency,
+                ref address,
+            } => write!(f, "Checksum of {} address {} does not match", currency, address),
+            AddressError::UnknownVersion { ref currency, version } => {
+                write!(f, "Unknown {} address version {:#04x}", currency, version)
+            }
+            AddressError::WrongNetwork {
+                ref currency,
+                ref prefix,
+            } => write!(f, "Address prefix {} does not belong to {}", prefix, currency),
+        }
+    }
+}
+
+/// Validate an address for a currency e.g. ('BTC', '1BoatSLRHtKNngkdXEeobR76b53LETtpyT')
+///
+/// Unknown currencies pass as `AddressCheck::Unchecked` with a warning.
+pub fn validate_address(currency: &str, address: &str) -> Result<AddressCheck, AddressError> {
+    let format = match coin_format(currency) {
+        Some(format) => format,
+        None => {
+            tracing::warn!(
+                address = MASK,
+                "No address format known for {}, the address is not validated",
+                currency
+            );
+            return Ok(AddressCheck::Unchecked);
+        }
+    };
+    let invalid = || AddressError::InvalidFormat {
+        currency: currency.to_string(),
+        address: address.to_string(),
+    };
+    let bad_checksum = || AddressError::BadChecksum {
+        currency: currency.to_string(),
+        address: address.to_string(),
+    };
+
+    if format.hex {
+        return match check_hex(address) {
+            Some(true) => Ok(AddressCheck::Valid),
+            Some(false) => Err(bad_checksum()),
+            None => Err(invalid()),
+        };
+    }
+
+    if let Some(hrp) = format.bech32 {
+        if let Some(segwit) = decode_segwit(address) {
+            if segwit.hrp == hrp {
+                return if segwit.checksum_valid {
+                    Ok(AddressCheck::Valid)
+                } else {
+                    Err(bad_checksum())
+                };
+            }
+            // A well formed segwit address of another chain or network
+            if segwit.checksum_valid {
+                return Err(AddressError::WrongNetwork {
+                    currency: currency.to_string(),
+                    prefix: segwit.hrp,
+                });
+            }
+        } else if let Some(separator) = address.rfind('1') {
+            if address[..separator].eq_ignore_ascii_case(hrp) {
+                return Err(invalid());
+            }
+        }
+    }
+
+    let decoded = base58_decode(address).ok_or_else(invalid)?;
+    if decoded.len() != 25 {
+        return Err(invalid());
+    }
+    let (payload, checksum) = decoded.split_at(21);
+    if &double_sha256(payload)[..4] != checksum {
+        return Err(bad_checksum());
+    }
+    if !format.base58.contains(&payload[0]) {
+        return Err(AddressError::UnknownVersion {
+            currency: currency.to_string(),
+            version: payload[0],
+        });
+    }
+    Ok(AddressCheck::Valid)
+}
+
+/// Validate an address returned by `generate_address`
+pub fn validate_deposit_address(address: &Address) -> Result<AddressCheck, AddressError> {
+    validate_address(&address.currency, &address.address)
+}
+
+/// Validate the deposit address of a balance, balances without address are `Unchecked`
+pub fn validate_balance_address(balance: &Balance) -> Result<AddressCheck, AddressError> {
+    match balance.address {
+        Some(ref address) => validate_address(&balance.currency, address),
+        None => Ok(AddressCheck::Unchecked),
+    }
+}
+
+fn double_sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(&Sha256::digest(data)).to_vec()
+}
+
+fn base58_decode(input: &str) -> Option<Vec<u8>> {
+    // Little endian big number
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    // Leading '1's are leading zero bytes
+    let zeros = input.bytes().take_while(|&c| c == b'1').count();
+    bytes.resize(bytes.len() + zeros, 0);
+    bytes.reverse();
+    Some(bytes)
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut chk: u32 = 1;
+    for &value in values {
+        let top = chk >> 25;
+        chk = (chk & 0x01ff_ffff) << 5 ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+/// Decoded bech32 or bech32m segwit address
+struct Segwit {
+    hrp: String,
+    /// Whether the checksum matches the encoding the witness version requires
+    checksum_valid: bool,
+}
+
+/// None if the address is not a well formed segwit address
+fn decode_segwit(address: &str) -> Option<Segwit> {
+    if address.len() > 90 || (address.to_lowercase() != address && address.to_uppercase() != address)
+    {
+        return None;
+    }
+    let address = address.to_lowercase();
+    let separator = address.rfind('1')?;
+    let hrp = &address[..separator];
+    if hrp.is_empty() || !hrp.bytes().all(|c| (33..=126).contains(&c)) {
+        return None;
+    }
+    let data: Vec<u8> = address[separator + 1..]
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|&a| a == c).map(|p| p as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if data.len() < 7 {
+        return None;
+    }
+
+    let version = data[0];
+    let program = convert_bits(&data[1..data.len() - 6])?;
+    if version > 16 || program.len() < 2 || program.len() > 40 {
+        return None;
+    }
+    if version == 0 && program.len() != 20 && program.len() != 32 {
+        return None;
+    }
+
+    let mut values: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|c| c & 31));
+    values.extend(&data);
+    let expected = if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    };
+    Some(Segwit {
+        hrp: hrp.to_string(),
+        checksum_valid: bech32_polymod(&values) == expected,
+    })
+}
+
+/// Regroup 5 bit values to bytes
+fn convert_bits(data: &[u8]) -> Option<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    let mut result = Vec::new();
+    for &value in data {
+        acc = (acc << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((acc >> bits & 0xff) as u8);
+        }
+    }
+    if bits >= 5 || (acc << (8 - bits)) & 0xff != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+/// None if the address is malformed, otherwise whether the mixed case checksum matches
+fn check_hex(address: &str) -> Option<bool> {
+    if !address.starts_with("0x") || address.len() != 42 {
+        return None;
+    }
+    let hex = &address[2..];
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let lower = hex.to_lowercase();
+    if hex == lower || hex == hex.to_uppercase() {
+        return Some(true);
+    }
+    let hash = keccak256(lower.as_bytes());
+    let valid = hex.chars().enumerate().all(|(i, c)| {
+        let nibble = if i % 2 == 0 {
+            hash[i / 2] >> 4
+        } else {
+            hash[i / 2] & 0x0f
+        };
+        if c.is_ascii_digit() {
+            true
+        } else if nibble >= 8 {
+            c.is_ascii_uppercase()
+        } else {
+            c.is_ascii_lowercase()
+        }
+    });
+    Some(valid)
+}
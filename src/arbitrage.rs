@@ -0,0 +1,272 @@
+use chrono::{DateTime, Utc};
+
+use std::collections::HashMap;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use graph::{Edge, MarketGraph};
+use query::{Client, Result};
+use routing::{simulate_hop, Hop};
+use valuation::Valuer;
+use values::*;
+
+/// Triangle A -> B -> C -> A returning more than it started with
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub time: DateTime<Utc>,
+    pub input: f32,
+    pub output: f32,
+    pub profit_pct: f32,
+    pub hops: Vec<Hop>,
+}
+
+impl Opportunity {
+    pub fn currency(&self) -> &str {
+        &self.hops[0].edge.from
+    }
+
+    pub fn profit(&self) -> f32 {
+        self.output - self.input
+    }
+}
+
+impl fmt::Display for Opportunity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let currencies: Vec<&str> = self.hops.iter().map(|hop| hop.edge.from.as_str()).collect();
+        let markets: Vec<&str> = self.hops.iter().map(|hop| hop.edge.market.as_str()).collect();
+        write!(
+            f,
+            "{} {} -> {}: {} -> {} ({:+.3}%) via {}",
+            self.time.to_rfc3339(),
+            currencies.join(" -> "),
+            self.currency(),
+            self.input,
+            self.output,
+            self.profit_pct,
+            markets.join(", ")
+        )
+    }
+}
+
+/// Scans the triangles of every currency for arbitrage after taker fees and order book depth
+///
+/// Only triangles starting at a currency with a trade size are scanned, see `size`
+/// and `reference_size`. Each triangle is reported once, from its first sized
+/// currency in alphabetical order.
+pub struct Scanner<'a> {
+    client: &'a Client,
+    sizes: HashMap<String, f32>,
+    /// (currency, amount) sizing currencies without a `size` at the value of the amount
+    reference_size: Option<(String, f32)>,
+    fee: f32,
+    threshold_pct: f32,
+    depth: u32,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Scanner {
+            client,
+            sizes: HashMap::new(),
+            reference_size: None,
+            fee: 0.002,
+            threshold_pct: 0.0,
+            depth: 50,
+        }
+    }
+
+    /// Trade size for triangles starting at this currency e.g. ('BTC', 0.01)
+    pub fn size(mut self, currency: &str, size: f32) -> Self {
+        self.sizes.insert(currency.to_string(), size);
+        self
+    }
+
+    /// Trade size in a reference currency for currencies without a `size` e.g. ('BTC', 0.01)
+    /// (default: none, only currencies with a `size` are scanned)
+    ///
+    /// The size is converted at the last prices, currencies without a priced route are skipped.
+    pub fn reference_size(mut self, currency: &str, size: f32) -> Self {
+        self.reference_size = Some((currency.to_string(), size));
+        self
+    }
+
+    /// Taker fee per trade as a fraction (default: 0.002)
+    pub fn fee(mut self, fee: f32) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Minimum profit in percent to report (default: 0)
+    pub fn threshold(mut self, threshold_pct: f32) -> Self {
+        self.threshold_pct = threshold_pct;
+        self
+    }
+
+    /// Order book depth fetched per market (default: 50)
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Scan once, returns opportunities above the threshold, most profitable first
+    pub fn scan(&self) -> Result<Vec<Opportunity>> {
+        let graph = MarketGraph::from_client(self.client)?;
+        let mut books: HashMap<String, PublicOrderBook> = HashMap::new();
+        let mut opportunities = Vec::new();
+
+        for (triangle, size) in self.triangles(&graph) {
+            if let Some(opportunity) = self.simulate(&mut books, &triangle, size)? {
+                opportunities.push(opportunity);
+            }
+        }
+        opportunities.sort_by(|a, b| {
+            b.profit_pct
+                .partial_cmp(&a.profit_pct)
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        });
+        Ok(opportunities)
+    }
+
+    /// Scan every interval and yield opportunities as they are found
+    pub fn stream(&self, interval: Duration) -> Opportunities<'_, 'a> {
+        Opportunities {
+            scanner: self,
+            interval,
+            pending: VecDeque::new(),
+            last_scan: None,
+        }
+    }
+
+    /// Sized triangles profitable at the top of book, each rotation of a triangle once
+    pub(crate) fn triangles(&self, graph: &MarketGraph) -> Vec<(Vec<Edge>, f32)> {
+        let mut currencies = graph.currencies();
+        currencies.sort();
+        let mut seen = HashSet::new();
+        let mut triangles = Vec::new();
+        for currency in currencies {
+            let size = match self.size_of(graph, currency) {
+                Some(size) => size,
+                None => continue,
+            };
+            for triangle in graph.paths(currency, currency, 3) {
+                if triangle.len() != 3 || !seen.insert(canonical_rotation(&triangle)) {
+                    continue;
+                }
+                // Depth only makes prices worse, skip triangles unprofitable at the top of book
+                match self.top_of_book_rate(graph, &triangle) {
+                    Some(rate) if (rate - 1.0) * 100.0 >= self.threshold_pct => {}
+                    _ => continue,
+                }
+                triangles.push((triangle, size));
+            }
+        }
+        triangles
+    }
+
+    /// Trade size in units of the currency, None when it can not be sized
+    fn size_of(&self, graph: &MarketGraph, currency: &str) -> Option<f32> {
+        if let Some(&size) = self.sizes.get(currency) {
+            return Some(size);
+        }
+        let (ref reference, size) = *self.reference_size.as_ref()?;
+        let (_, rate) = Valuer::new(reference).route(graph, currency)?;
+        Some(size / rate)
+    }
+
+    fn top_of_book_rate(&self, graph: &MarketGraph, triangle: &[Edge]) -> Option<f32> {
+        let mut rate = 1.0;
+        for edge in triangle {
+            let summary = graph.summary(&edge.market)?;
+            let price = if edge.sell { summary.bid } else { summary.ask };
+            if price <= 0.0 {
+                return None;
+            }
+            rate *= if edge.sell { price } else { 1.0 / price };
+            rate *= 1.0 - self.fee;
+        }
+        Some(rate)
+    }
+
+    fn simulate(
+        &self,
+        books: &mut HashMap<String, PublicOrderBook>,
+        triangle: &[Edge],
+        size: f32,
+    ) -> Result<Option<Opportunity>> {
+        let mut input = size;
+        let mut hops = Vec::new();
+        for edge in triangle {
+            if !books.contains_key(&edge.market) {
+                let book = self
+                    .client
+                    .get_order_book(edge.market.clone(), None, Some(self.depth))?;
+                books.insert(edge.market.clone(), book);
+            }
+            match simulate_hop(edge, &books[&edge.market], input, self.fee) {
+                Some(hop) => {
+                    input = hop.output;
+                    hops.push(hop);
+                }
+                None => return Ok(None),
+            }
+        }
+        let profit_pct = (input - size) / size * 100.0;
+        if profit_pct < self.threshold_pct {
+            return Ok(None);
+        }
+        Ok(Some(Opportunity {
+            time: Utc::now(),
+            input: size,
+            output: input,
+            profit_pct,
+            hops,
+        }))
+    }
+}
+
+/// Endless stream of opportunities, see `Scanner::stream`
+pub struct Opportunities<'s, 'a: 's> {
+    scanner: &'s Scanner<'a>,
+    interval: Duration,
+    pending: VecDeque<Opportunity>,
+    last_scan: Option<Instant>,
+}
+
+impl<'s, 'a> Iterator for Opportunities<'s, 'a> {
+    type Item = Result<Opportunity>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(opportunity) = self.pending.pop_front() {
+                return Some(Ok(opportunity));
+            }
+            if let Some(last_scan) = self.last_scan {
+                let elapsed = last_scan.elapsed();
+                if elapsed < self.interval {
+                    thread::sleep(self.interval - elapsed);
+                }
+            }
+            self.last_scan = Some(Instant::now());
+            match self.scanner.scan() {
+                Ok(opportunities) => self.pending.extend(opportunities),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+/// Markets and directions of a triangle rotated to start at its smallest currency
+fn canonical_rotation(triangle: &[Edge]) -> Vec<(String, bool)> {
+    let start = (0..triangle.len())
+        .min_by_key(|&index| &triangle[index].from)
+        .unwrap_or(0);
+    triangle
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(triangle.len())
+        .map(|edge| (edge.market.clone(), edge.sell))
+        .collect()
+}
@@ -0,0 +1,444 @@
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+extern crate tradesatoshi_api;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde::Serialize;
+use serde_json::Value;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use tradesatoshi_api::credentials::{Credentials, KEY_VAR, SECRET_VAR};
+use tradesatoshi_api::error::{Error, ErrorType};
+use tradesatoshi_api::Client;
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn main() {
+    let matches = app().get_matches();
+    if let Err(error) = run(&matches) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let market = || Arg::with_name("market").required(true).help("Market e.g. LTC_BTC");
+    let currency = || Arg::with_name("currency").required(true).help("Currency e.g. BTC");
+    let count = || {
+        Arg::with_name("count")
+            .long("count")
+            .takes_value(true)
+            .help("Max records to return")
+    };
+    let market_filter = || {
+        Arg::with_name("market")
+            .long("market")
+            .takes_value(true)
+            .help("Market e.g. LTC_BTC (default: all)")
+    };
+    let currency_filter = || {
+        Arg::with_name("currency")
+            .long("currency")
+            .takes_value(true)
+            .help("Currency e.g. BTC (default: all)")
+    };
+
+    App::new("tscli")
+        .about("Command line client for the Tradesatoshi API")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print JSON instead of a table"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .global(true)
+                .help("JSON file with api_key and api_secret (default: ~/.tscli.json)"),
+        )
+        .subcommand(SubCommand::with_name("currencies").about("List currencies"))
+        .subcommand(
+            SubCommand::with_name("ticker")
+                .about("Show the ticker of a market")
+                .arg(market()),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Show the latest trades of a market")
+                .arg(market())
+                .arg(count()),
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Show the summary of a market")
+                .arg(market()),
+        )
+        .subcommand(SubCommand::with_name("summaries").about("Show the summaries of all markets"))
+        .subcommand(
+            SubCommand::with_name("book")
+                .about("Show the order book of a market")
+                .arg(market())
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .takes_value(true)
+                        .possible_values(&["buy", "sell", "both"])
+                        .help("Order book side (default: both)"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .help("Max orders per side (default: 20)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("balance")
+                .about("Show the balance of a currency")
+                .arg(currency()),
+        )
+        .subcommand(SubCommand::with_name("balances").about("Show all balances"))
+        .subcommand(
+            SubCommand::with_name("order")
+                .about("Show and submit orders")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .about("Show an order")
+                        .arg(Arg::with_name("id").required(true).help("Order id")),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List open orders")
+                        .arg(market_filter())
+                        .arg(count()),
+                )
+                .subcommand(
+                    SubCommand::with_name("submit")
+                        .about("Submit an order")
+                        .arg(market())
+                        .arg(
+                            Arg::with_name("type")
+                                .required(true)
+                                .possible_values(&["Buy", "Sell"])
+                                .help("Order type"),
+                        )
+                        .arg(Arg::with_name("amount").required(true).help("Amount to buy/sell"))
+                        .arg(Arg::with_name("price").required(true).help("Price to buy/sell for")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cancel")
+                .about("Cancel orders")
+                .arg(Arg::with_name("id").help("Order to cancel"))
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .conflicts_with_all(&["id", "market"])
+                        .help("Cancel all orders"),
+                )
+                .arg(
+                    Arg::with_name("market")
+                        .long("market")
+                        .takes_value(true)
+                        .conflicts_with("id")
+                        .help("Cancel the orders of a market"),
+                )
+                .arg(
+                    Arg::with_name("side")
+                        .long("side")
+                        .takes_value(true)
+                        .possible_values(&["buys", "sells"])
+                        .conflicts_with("id")
+                        .help("Only cancel buys or sells"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trades")
+                .about("Show your trade history")
+                .arg(market_filter())
+                .arg(count())
+                .arg(
+                    Arg::with_name("page")
+                        .long("page")
+                        .takes_value(true)
+                        .help("Page number (default: 0)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("address")
+                .about("Generate a deposit address")
+                .arg(currency()),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw")
+                .about("Withdraw to an address")
+                .arg(currency())
+                .arg(Arg::with_name("address").required(true).help("Destination address"))
+                .arg(Arg::with_name("amount").required(true).help("Amount to withdraw")),
+        )
+        .subcommand(
+            SubCommand::with_name("transfer")
+                .about("Transfer to another Tradesatoshi user")
+                .arg(currency())
+                .arg(Arg::with_name("username").required(true).help("Receiving username"))
+                .arg(Arg::with_name("amount").required(true).help("Amount to transfer")),
+        )
+        .subcommand(
+            SubCommand::with_name("deposits")
+                .about("Show your deposits")
+                .arg(currency_filter())
+                .arg(count()),
+        )
+        .subcommand(
+            SubCommand::with_name("withdrawals")
+                .about("Show your withdrawals")
+                .arg(currency_filter())
+                .arg(count()),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let json = matches.is_present("json");
+    let (name, args) = matches.subcommand();
+    let args = match args {
+        Some(args) => args,
+        None => return Ok(()),
+    };
+    let json = json || args.is_present("json");
+    let client = client(args.value_of("config").or_else(|| matches.value_of("config")))?;
+
+    let string = |name: &str| args.value_of(name).map(|value| value.to_string());
+    match name {
+        "currencies" => print(&client.get_currencies()?, json),
+        "ticker" => print(&client.get_ticker(required(args, "market"))?, json),
+        "history" => print(
+            &client.get_market_history(required(args, "market"), parse_opt(args, "count")?)?,
+            json,
+        ),
+        "summary" => print(&client.get_market_summary(required(args, "market"))?, json),
+        "summaries" => print(&client.get_market_summaries()?, json),
+        "book" => print(
+            &client.get_order_book(
+                required(args, "market"),
+                string("type"),
+                parse_opt(args, "depth")?,
+            )?,
+            json,
+        ),
+        "balance" => print(&client.get_balance(required(args, "currency"))?, json),
+        "balances" => print(&client.get_balances()?, json),
+        "order" => order(&client, args, json),
+        "cancel" => {
+            let (typeo, id, market) = if args.is_present("all") {
+                let typeo = match args.value_of("side") {
+                    Some("buys") => "AllBuys",
+                    Some("sells") => "AllSells",
+                    _ => "All",
+                };
+                (typeo, None, None)
+            } else if let Some(market) = string("market") {
+                let typeo = match args.value_of("side") {
+                    Some("buys") => "MarketBuys",
+                    Some("sells") => "MarketSells",
+                    _ => "Market",
+                };
+                (typeo, None, Some(market))
+            } else {
+                match parse_opt(args, "id")? {
+                    Some(id) => ("Single", Some(id), None),
+                    None => return Err(usage("cancel needs an order id, --market or --all")),
+                }
+            };
+            print(&client.cancel_order(typeo.to_string(), id, market)?, json)
+        }
+        "trades" => print(
+            &client.get_trade_history(
+                string("market"),
+                parse_opt(args, "count")?,
+                parse_opt(args, "page")?,
+            )?,
+            json,
+        ),
+        "address" => print(&client.generate_address(required(args, "currency"))?, json),
+        "withdraw" => print(
+            &client.submit_withdraw(
+                required(args, "currency"),
+                required(args, "address"),
+                parse(args, "amount")?,
+            )?,
+            json,
+        ),
+        "transfer" => print(
+            &client.submit_transfer(
+                required(args, "currency"),
+                required(args, "username"),
+                parse(args, "amount")?,
+            )?,
+            json,
+        ),
+        "deposits" => print(
+            &client.get_deposits(string("currency"), parse_opt(args, "count")?)?,
+            json,
+        ),
+        "withdrawals" => print(
+            &client.get_withdrawals(string("currency"), parse_opt(args, "count")?)?,
+            json,
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn order(client: &Client, matches: &ArgMatches, json: bool) -> Result<()> {
+    let (name, args) = matches.subcommand();
+    let args = match args {
+        Some(args) => args,
+        None => return Ok(()),
+    };
+    let json = json || args.is_present("json");
+    match name {
+        "get" => print(&client.get_order(parse(args, "id")?)?, json),
+        "list" => print(
+            &client.get_orders(
+                args.value_of("market").map(|market| market.to_string()),
+                parse_opt(args, "count")?,
+            )?,
+            json,
+        ),
+        "submit" => print(
+            &client.submit_order(
+                required(args, "market"),
+                required(args, "type"),
+                parse(args, "amount")?,
+                parse(args, "price")?,
+            )?,
+            json,
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Credentials from the environment, then the config file
+///
+/// Public commands work without credentials.
+fn client(config: Option<&str>) -> Result<Client> {
+    if env::var_os(KEY_VAR).is_some() && env::var_os(SECRET_VAR).is_some() {
+        return Ok(Client::with_credentials(Credentials::from_env()?));
+    }
+    let path = match config {
+        Some(path) => PathBuf::from(path),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".tscli.json"),
+            None => return Client::new(String::new(), String::new()),
+        },
+    };
+    if config.is_none() && !path.exists() {
+        return Client::new(String::new(), String::new());
+    }
+    Ok(Client::with_credentials(Credentials::from_file(&path)?))
+}
+
+fn required(args: &ArgMatches, name: &str) -> String {
+    args.value_of(name).unwrap_or_default().to_string()
+}
+
+fn parse<T: FromStr>(args: &ArgMatches, name: &str) -> Result<T> {
+    parse_opt(args, name)?.ok_or_else(|| usage(&format!("missing {}", name)))
+}
+
+fn parse_opt<T: FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>> {
+    match args.value_of(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| usage(&format!("invalid {}: {}", name, value))),
+        None => Ok(None),
+    }
+}
+
+fn usage(message: &str) -> Error {
+    Error {
+        error_type: ErrorType::ValidationError,
+        message: message.to_string(),
+    }
+}
+
+fn print<T: Serialize>(value: &T, json: bool) -> Result<()> {
+    let value = serde_json::to_value(value)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        print_table(&value);
+    }
+    Ok(())
+}
+
+/// Arrays of objects print as rows, objects as key/value pairs
+fn print_table(value: &Value) {
+    match *value {
+        Value::Array(ref rows) => {
+            let columns: Vec<String> = match rows.first() {
+                Some(Value::Object(ref first)) => first.keys().cloned().collect(),
+                _ => {
+                    for row in rows {
+                        println!("{}", cell(row));
+                    }
+                    return;
+                }
+            };
+            let cells: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| columns.iter().map(|column| cell(&row[column])).collect())
+                .collect();
+            let mut widths: Vec<usize> = columns.iter().map(|column| column.len()).collect();
+            for row in &cells {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            print_row(&columns, &widths);
+            for row in &cells {
+                print_row(row, &widths);
+            }
+        }
+        Value::Object(ref fields) => {
+            // Nested arrays e.g. the sides of an order book print as their own tables
+            let width = fields.keys().map(|key| key.len()).max().unwrap_or(0);
+            for (key, field) in fields {
+                if let Value::Array(_) = *field {
+                    println!("{}:", key);
+                    print_table(field);
+                    println!();
+                } else {
+                    println!("{:width$}  {}", key, cell(field), width = width);
+                }
+            }
+        }
+        ref other => println!("{}", cell(other)),
+    }
+}
+
+fn print_row(cells: &[String], widths: &[usize]) {
+    let line: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+        .collect();
+    println!("{}", line.join("  ").trim_end());
+}
+
+fn cell(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref string) => string.clone(),
+        ref other => other.to_string(),
+    }
+}
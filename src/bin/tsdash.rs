@@ -0,0 +1,483 @@
+extern crate termion;
+extern crate tradesatoshi_api;
+extern crate tui;
+
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use tui::backend::{Backend, TermionBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use tui::{Frame, Terminal};
+
+use std::cmp::Ordering;
+use std::io;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tradesatoshi_api::credentials::Credentials;
+use tradesatoshi_api::values::*;
+use tradesatoshi_api::Client;
+
+const REFRESH: Duration = Duration::from_secs(5);
+const BOOK_DEPTH: u32 = 20;
+const TRADE_COUNT: u32 = 30;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Volume,
+    Change,
+    Name,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Markets,
+    Orders,
+}
+
+struct App {
+    client: Client,
+    summaries: Vec<MarketSummary>,
+    sort: SortBy,
+    markets: TableState,
+    book: Option<PublicOrderBook>,
+    trades: Vec<Trade>,
+    balances: Vec<Balance>,
+    orders: Vec<Order>,
+    open_orders: TableState,
+    focus: Focus,
+    /// Order waiting for the cancel confirmation
+    pending_cancel: Option<u32>,
+    status: String,
+}
+
+impl App {
+    fn new(client: Client) -> Self {
+        App {
+            client,
+            summaries: Vec::new(),
+            sort: SortBy::Volume,
+            markets: TableState::default(),
+            book: None,
+            trades: Vec::new(),
+            balances: Vec::new(),
+            orders: Vec::new(),
+            open_orders: TableState::default(),
+            focus: Focus::Markets,
+            pending_cancel: None,
+            status: String::new(),
+        }
+    }
+
+    fn selected_market(&self) -> Option<String> {
+        self.markets
+            .selected()
+            .and_then(|index| self.summaries.get(index))
+            .map(|summary| summary.market.clone())
+    }
+
+    fn selected_order(&self) -> Option<&Order> {
+        self.open_orders
+            .selected()
+            .and_then(|index| self.orders.get(index))
+    }
+
+    /// Reload everything, errors end up in the status line
+    fn refresh(&mut self) {
+        let selected = self.selected_market();
+        match self.client.get_market_summaries() {
+            Ok(summaries) => {
+                self.summaries = summaries;
+                self.sort_markets(selected);
+            }
+            Err(error) => self.status = error.to_string(),
+        }
+        self.refresh_market();
+        self.refresh_account();
+    }
+
+    fn refresh_market(&mut self) {
+        let market = match self.selected_market() {
+            Some(market) => market,
+            None => return,
+        };
+        match self
+            .client
+            .get_order_book(market.clone(), None, Some(BOOK_DEPTH))
+        {
+            Ok(book) => self.book = Some(book),
+            Err(error) => self.status = error.to_string(),
+        }
+        match self.client.get_market_history(market, Some(TRADE_COUNT)) {
+            Ok(trades) => self.trades = trades,
+            Err(error) => self.status = error.to_string(),
+        }
+    }
+
+    fn refresh_account(&mut self) {
+        match self.client.get_balances() {
+            Ok(balances) => {
+                self.balances = balances
+                    .into_iter()
+                    .filter(|balance| balance.total > 0.0)
+                    .collect()
+            }
+            Err(error) => self.status = error.to_string(),
+        }
+        let selected = self.selected_order().map(|order| order.id);
+        match self.client.get_orders(None, Some(100)) {
+            Ok(orders) => {
+                self.orders = orders;
+                let index = selected
+                    .and_then(|id| self.orders.iter().position(|order| order.id == id))
+                    .or(if self.orders.is_empty() { None } else { Some(0) });
+                self.open_orders.select(index);
+            }
+            Err(error) => self.status = error.to_string(),
+        }
+    }
+
+    /// Sort the market list and keep the selection on the same market
+    fn sort_markets(&mut self, selected: Option<String>) {
+        match self.sort {
+            SortBy::Volume => self.summaries.sort_by(|a, b| {
+                b.base_volume
+                    .partial_cmp(&a.base_volume)
+                    .unwrap_or(Ordering::Equal)
+            }),
+            SortBy::Change => self
+                .summaries
+                .sort_by(|a, b| b.change.partial_cmp(&a.change).unwrap_or(Ordering::Equal)),
+            SortBy::Name => self.summaries.sort_by(|a, b| a.market.cmp(&b.market)),
+        }
+        let index = selected
+            .and_then(|market| {
+                self.summaries
+                    .iter()
+                    .position(|summary| summary.market == market)
+            })
+            .or(if self.summaries.is_empty() { None } else { Some(0) });
+        self.markets.select(index);
+    }
+
+    fn move_selection(&mut self, step: isize) {
+        let (state, len) = match self.focus {
+            Focus::Markets => (&mut self.markets, self.summaries.len()),
+            Focus::Orders => (&mut self.open_orders, self.orders.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        let next = (current + step).max(0).min(len as isize - 1);
+        state.select(Some(next as usize));
+        if self.focus == Focus::Markets {
+            self.book = None;
+            self.trades.clear();
+            self.refresh_market();
+        }
+    }
+
+    /// Handle a key press, returns false to quit
+    fn on_key(&mut self, key: Key) -> bool {
+        if let Some(order_id) = self.pending_cancel.take() {
+            if key == Key::Char('y') {
+                self.status = match self
+                    .client
+                    .cancel_order("Single".to_string(), Some(order_id), None)
+                {
+                    Ok(_) => format!("Cancelled order {}", order_id),
+                    Err(error) => error.to_string(),
+                };
+                self.refresh_account();
+            } else {
+                self.status = "Cancel aborted".to_string();
+            }
+            return true;
+        }
+
+        match key {
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => return false,
+            Key::Char('\t') => {
+                self.focus = match self.focus {
+                    Focus::Markets => Focus::Orders,
+                    Focus::Orders => Focus::Markets,
+                }
+            }
+            Key::Up | Key::Char('k') => self.move_selection(-1),
+            Key::Down | Key::Char('j') => self.move_selection(1),
+            Key::PageUp => self.move_selection(-10),
+            Key::PageDown => self.move_selection(10),
+            Key::Char('s') => {
+                self.sort = match self.sort {
+                    SortBy::Volume => SortBy::Change,
+                    SortBy::Change => SortBy::Name,
+                    SortBy::Name => SortBy::Volume,
+                };
+                let selected = self.selected_market();
+                self.sort_markets(selected);
+            }
+            Key::Char('r') => self.refresh(),
+            Key::Char('c') if self.focus == Focus::Orders => {
+                let order = self
+                    .selected_order()
+                    .map(|order| (order.id, order.market.clone(), order.order_type.clone()));
+                if let Some((id, market, order_type)) = order {
+                    self.status = format!("Cancel {} order {} on {}? (y/n)", order_type, id, market);
+                    self.pending_cancel = Some(id);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> io::Result<()> {
+    let client = Credentials::from_env()
+        .map(Client::with_credentials)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+    let (keys_tx, keys) = mpsc::channel();
+    thread::spawn(move || {
+        for key in io::stdin().keys().flatten() {
+            if keys_tx.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    let stdout = AlternateScreen::from(io::stdout().into_raw_mode()?);
+    let mut terminal = Terminal::new(TermionBackend::new(stdout))?;
+    terminal.hide_cursor()?;
+
+    let mut app = App::new(client);
+    app.refresh();
+    let mut last_refresh = Instant::now();
+    loop {
+        terminal.draw(|f| draw(f, &mut app))?;
+        match keys.recv_timeout(REFRESH.checked_sub(last_refresh.elapsed()).unwrap_or_default()) {
+            Ok(key) => {
+                if !app.on_key(key) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if last_refresh.elapsed() >= REFRESH {
+            app.refresh();
+            last_refresh = Instant::now();
+        }
+    }
+    terminal.show_cursor()
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage(60),
+                Constraint::Min(6),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
+        )
+        .split(f.size());
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(rows[0]);
+    let market = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(top[1]);
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(rows[1]);
+
+    draw_markets(f, app, top[0]);
+    draw_book(f, app, market[0]);
+    draw_trades(f, app, market[1]);
+    draw_balances(f, app, bottom[0]);
+    draw_orders(f, app, bottom[1]);
+
+    let help = "q quit  tab switch pane  up/down select  s sort  r refresh  c cancel order";
+    let status = if app.status.is_empty() {
+        help.to_string()
+    } else {
+        format!("{}  |  {}", app.status, help)
+    };
+    f.render_widget(Paragraph::new(status), rows[2]);
+}
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn header(columns: &[&'static str]) -> Row<'static> {
+    Row::new(columns.to_vec()).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn highlight() -> Style {
+    Style::default().add_modifier(Modifier::REVERSED)
+}
+
+fn draw_markets<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let sort = match app.sort {
+        SortBy::Volume => "volume",
+        SortBy::Change => "change",
+        SortBy::Name => "name",
+    };
+    let rows = app.summaries.iter().map(|summary| {
+        Row::new(vec![
+            summary.market.clone(),
+            format!("{:.8}", summary.last),
+            format!("{:+.2}%", summary.change),
+            format!("{:.4}", summary.base_volume),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header(&["Market", "Last", "Change", "Volume"]))
+        .block(block(
+            format!("Markets (by {})", sort),
+            app.focus == Focus::Markets,
+        ))
+        .widths(&[
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(9),
+            Constraint::Min(10),
+        ])
+        .highlight_style(highlight());
+    f.render_stateful_widget(table, area, &mut app.markets);
+}
+
+fn draw_book<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let title = format!("Order book {}", app.selected_market().unwrap_or_default());
+    let rows: Vec<Row> = match app.book {
+        Some(ref book) => {
+            let depth = book.buy.len().max(book.sell.len());
+            (0..depth)
+                .map(|index| {
+                    let level = |orders: &[PublicOrder]| {
+                        orders.get(index).map_or((String::new(), String::new()), |order| {
+                            (format!("{:.8}", order.quantity), format!("{:.8}", order.rate))
+                        })
+                    };
+                    let (bid_quantity, bid) = level(&book.buy);
+                    let (ask_quantity, ask) = level(&book.sell);
+                    Row::new(vec![bid_quantity, bid, ask, ask_quantity])
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let table = Table::new(rows)
+        .header(header(&["Bid size", "Bid", "Ask", "Ask size"]))
+        .block(block(title, false))
+        .widths(&[
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+        ]);
+    f.render_widget(table, area);
+}
+
+fn draw_trades<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let rows = app.trades.iter().map(|trade| {
+        let style = if trade.order_type.eq_ignore_ascii_case("buy") {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default().fg(Color::Red)
+        };
+        Row::new(vec![
+            format!("{:.8}", trade.price),
+            format!("{:.8}", trade.quantity),
+            trade.time_stamp.chars().skip(11).take(8).collect(),
+        ])
+        .style(style)
+    });
+    let table = Table::new(rows)
+        .header(header(&["Price", "Quantity", "Time"]))
+        .block(block("Trades".to_string(), false))
+        .widths(&[
+            Constraint::Percentage(35),
+            Constraint::Percentage(35),
+            Constraint::Percentage(30),
+        ]);
+    f.render_widget(table, area);
+}
+
+fn draw_balances<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let rows = app.balances.iter().map(|balance| {
+        Row::new(vec![
+            balance.currency.clone(),
+            format!("{:.8}", balance.available),
+            format!("{:.8}", balance.held_for_trades),
+            format!("{:.8}", balance.total),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header(&["Currency", "Available", "Held", "Total"]))
+        .block(block("Balances".to_string(), false))
+        .widths(&[
+            Constraint::Length(8),
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+        ]);
+    f.render_widget(table, area);
+}
+
+fn draw_orders<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let rows = app.orders.iter().map(|order| {
+        Row::new(vec![
+            order.id.to_string(),
+            order.market.clone(),
+            order.order_type.clone(),
+            format!("{:.8}", order.rate),
+            format!("{:.8}", order.amount),
+            format!("{:.8}", order.remaining),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header(&["Id", "Market", "Type", "Rate", "Amount", "Remaining"]))
+        .block(block(
+            "Open orders".to_string(),
+            app.focus == Focus::Orders,
+        ))
+        .widths(&[
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(5),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+        ])
+        .highlight_style(highlight());
+    f.render_stateful_widget(table, area, &mut app.open_orders);
+}
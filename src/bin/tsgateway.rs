@@ -0,0 +1,38 @@
+extern crate tracing;
+extern crate tracing_subscriber;
+extern crate tradesatoshi_api;
+
+use std::env;
+use std::io;
+use std::process;
+
+use tradesatoshi_api::gateway::{Gateway, GatewayConfig};
+
+fn main() {
+    // Call log to stderr
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "gateway.json".to_string());
+    let config = match GatewayConfig::from_file(&path) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+    if config.tokens.is_empty() {
+        eprintln!("{} has no tokens, every call would be rejected", path);
+        process::exit(1);
+    }
+
+    let listen = config.listen.clone();
+    if let Err(error) = Gateway::from_config(config).serve(&listen) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,263 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use query::{Client, Result};
+use values::*;
+
+/// Cached public endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Currencies,
+    Ticker,
+    MarketHistory,
+    MarketSummary,
+    MarketSummaries,
+    OrderBook,
+}
+
+type Key = (Endpoint, String);
+
+enum Slot {
+    /// A request is in flight, identified so a late response does not
+    /// overwrite what an invalidation or newer request left behind
+    Pending(u64),
+    Ready {
+        value: Arc<dyn Any + Send + Sync>,
+        expires: Instant,
+        request: u64,
+    },
+}
+
+struct Entries {
+    slots: HashMap<Key, Slot>,
+    next_request: u64,
+}
+
+/// Client caching public endpoints, private calls go through `client()` uncached
+///
+/// Concurrent callers asking for the same uncached data wait for a single
+/// request instead of each sending their own.
+pub struct CachedClient {
+    client: Client,
+    ttls: HashMap<Endpoint, Duration>,
+    entries: Mutex<Entries>,
+    ready: Condvar,
+}
+
+impl CachedClient {
+    pub fn new(client: Client) -> Self {
+        let ttls = [
+            (Endpoint::Currencies, Duration::from_secs(3600)),
+            (Endpoint::MarketSummaries, Duration::from_secs(30)),
+            (Endpoint::MarketSummary, Duration::from_secs(30)),
+            (Endpoint::MarketHistory, Duration::from_secs(5)),
+            (Endpoint::Ticker, Duration::from_secs(2)),
+            (Endpoint::OrderBook, Duration::from_secs(2)),
+        ];
+        CachedClient {
+            client,
+            ttls: ttls.iter().cloned().collect(),
+            entries: Mutex::new(Entries {
+                slots: HashMap::new(),
+                next_request: 0,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Time a response stays cached, zero disables caching but keeps coalescing
+    ///
+    /// Defaults: currencies 1h, market summaries 30s, market history 5s, ticker and order book 2s
+    pub fn ttl(mut self, endpoint: Endpoint, ttl: Duration) -> Self {
+        self.ttls.insert(endpoint, ttl);
+        self
+    }
+
+    /// Underlying client, e.g. for private calls
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Drop every cached response of an endpoint
+    pub fn invalidate(&self, endpoint: Endpoint) {
+        self.lock().slots.retain(|key, _| key.0 != endpoint);
+        self.ready.notify_all();
+    }
+
+    /// Drop the cached responses of an endpoint for one market
+    pub fn invalidate_market(&self, endpoint: Endpoint, market: &str) {
+        let prefix = format!("{}/", market);
+        self.lock()
+            .slots
+            .retain(|key, _| key.0 != endpoint || !key.1.starts_with(&prefix));
+        self.ready.notify_all();
+    }
+
+    /// Drop every cached response
+    pub fn clear(&self) {
+        self.lock().slots.clear();
+        self.ready.notify_all();
+    }
+
+    /// Get currencies, cached
+    pub fn get_currencies(&self) -> Result<Vec<Currency>> {
+        self.cached(Endpoint::Currencies, String::new(), |client| {
+            client.get_currencies()
+        })
+    }
+
+    /// Get ticker, cached per market
+    ///
+    /// market: The market name e.g. 'LTC_BTC' (required)
+    pub fn get_ticker(&self, market: String) -> Result<Ticker> {
+        self.cached(Endpoint::Ticker, format!("{}/", market), |client| {
+            client.get_ticker(market)
+        })
+    }
+
+    /// Get market history, cached per market and count
+    ///
+    /// market: The market name e.g. 'LTC_BTC' (required)
+    /// count: The max amount of records to return (optional, default: 20)
+    pub fn get_market_history(&self, market: String, count: Option<u32>) -> Result<Vec<Trade>> {
+        let key = format!("{}/{}", market, count.unwrap_or(20));
+        self.cached(Endpoint::MarketHistory, key, |client| {
+            client.get_market_history(market, count)
+        })
+    }
+
+    /// Get market summary, cached per market
+    ///
+    /// market: The market name e.g. 'LTC_BTC' (required)
+    pub fn get_market_summary(&self, market: String) -> Result<MarketSummary> {
+        self.cached(Endpoint::MarketSummary, format!("{}/", market), |client| {
+            client.get_market_summary(market)
+        })
+    }
+
+    /// Get market summaries, cached
+    pub fn get_market_summaries(&self) -> Result<Vec<MarketSummary>> {
+        self.cached(Endpoint::MarketSummaries, String::new(), |client| {
+            client.get_market_summaries()
+        })
+    }
+
+    /// Get order book, cached per market, type and depth
+    ///
+    /// market: The market name e.g. 'LTC_BTC' (required)
+    /// typeo: The order book type 'buy', 'sell', 'both' (optional, default: 'both')
+    /// depth: Max of records to return (optional, default: 20)
+    pub fn get_order_book(
+        &self,
+        market: String,
+        typeo: Option<String>,
+        depth: Option<u32>,
+    ) -> Result<PublicOrderBook> {
+        let key = format!(
+            "{}/{}/{}",
+            market,
+            typeo.as_ref().map_or("both", |typeo| typeo.as_str()),
+            depth.unwrap_or(20)
+        );
+        self.cached(Endpoint::OrderBook, key, |client| {
+            client.get_order_book(market, typeo, depth)
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().expect("Cache lock poisoned!")
+    }
+
+    pub(crate) fn cached<T, F>(&self, endpoint: Endpoint, params: String, fetch: F) -> Result<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce(&Client) -> Result<T>,
+    {
+        let key = (endpoint, params);
+        let mut entries = self.lock();
+        let mut waited_on = None;
+        loop {
+            match entries.slots.get(&key) {
+                // The response of a request we waited on is used even when it expired at once
+                Some(&Slot::Ready {
+                    ref value,
+                    expires,
+                    request,
+                }) if expires > Instant::now() || waited_on == Some(request) => {
+                    return Ok(value
+                        .downcast_ref::<T>()
+                        .expect("Cached value has the endpoint's type")
+                        .clone());
+                }
+                Some(&Slot::Pending(request)) => {
+                    waited_on = Some(request);
+                    entries = self.ready.wait(entries).expect("Cache lock poisoned!");
+                }
+                _ => break,
+            }
+        }
+        let request = entries.next_request;
+        entries.next_request += 1;
+        entries.slots.insert(key.clone(), Slot::Pending(request));
+        drop(entries);
+
+        let mut pending = PendingRequest {
+            cache: self,
+            key: Some(key),
+            request,
+        };
+        let result = fetch(&self.client);
+        let key = pending.key.take().expect("Request completes once");
+
+        let mut entries = self.lock();
+        if let Some(&Slot::Pending(current)) = entries.slots.get(&key) {
+            if current == request {
+                let ttl = self.ttls.get(&endpoint).cloned().unwrap_or_default();
+                match result {
+                    Ok(ref value) => {
+                        let value = Arc::new(value.clone());
+                        entries.slots.insert(
+                            key,
+                            Slot::Ready {
+                                value,
+                                expires: Instant::now() + ttl,
+                                request,
+                            },
+                        );
+                    }
+                    // Errors are not shared, waiting callers send their own request
+                    Err(_) => {
+                        entries.slots.remove(&key);
+                    }
+                }
+            }
+        }
+        drop(entries);
+        self.ready.notify_all();
+        result
+    }
+}
+
+/// Releases a pending slot if the request panics, so waiters do not block forever
+struct PendingRequest<'a> {
+    cache: &'a CachedClient,
+    key: Option<Key>,
+    request: u64,
+}
+
+impl<'a> Drop for PendingRequest<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            if let Ok(mut entries) = self.cache.entries.lock() {
+                if let Some(&Slot::Pending(current)) = entries.slots.get(&key) {
+                    if current == self.request {
+                        entries.slots.remove(&key);
+                    }
+                }
+            }
+            self.cache.ready.notify_all();
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use chrono::{DateTime, Duration, Utc};
+use rand;
+
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+/// How request nonces are generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceScheme {
+    /// Random digits
+    Random,
+    /// Milliseconds since the epoch, corrected for clock skew and always increasing
+    Timestamp,
+}
+
+/// Local view of the server clock, used for timestamp nonces
+///
+/// The skew is measured from the HTTP `Date` header of every response and
+/// applied to nonces once the server rejects one.
+pub struct Clock {
+    scheme: NonceScheme,
+    measured: AtomicBool,
+    skew_ms: AtomicI64,
+    offset_ms: AtomicI64,
+    last_nonce: AtomicI64,
+}
+
+impl Clock {
+    pub fn new(scheme: NonceScheme) -> Self {
+        Clock {
+            scheme,
+            measured: AtomicBool::new(false),
+            skew_ms: AtomicI64::new(0),
+            offset_ms: AtomicI64::new(0),
+            last_nonce: AtomicI64::new(0),
+        }
+    }
+
+    pub fn scheme(&self) -> NonceScheme {
+        self.scheme
+    }
+
+    /// Nonce for the next request
+    pub fn nonce(&self) -> String {
+        match self.scheme {
+            NonceScheme::Random => {
+                let randn: f64 = rand::random();
+                randn.to_string()[2..].to_string()
+            }
+            NonceScheme::Timestamp => {
+                let now = Utc::now().timestamp_millis() + self.offset_ms.load(Ordering::SeqCst);
+                let previous = self.last_nonce.fetch_max(now, Ordering::SeqCst);
+                // Two requests in the same millisecond or a correction back in time
+                if previous >= now {
+                    (self.last_nonce.fetch_add(1, Ordering::SeqCst) + 1).to_string()
+                } else {
+                    now.to_string()
+                }
+            }
+        }
+    }
+
+    /// Measure the skew from a response `Date` header
+    ///
+    /// sent: Local time the request was sent (required)
+    /// received: Local time the response arrived (required)
+    pub fn observe_server_time(
+        &self,
+        server: DateTime<Utc>,
+        sent: DateTime<Utc>,
+        received: DateTime<Utc>,
+    ) {
+        // The header has second resolution, take the middle of that second
+        // against the middle of the round trip
+        let server = server + Duration::milliseconds(500);
+        let local = sent + (received - sent) / 2;
+        self.skew_ms
+            .store((server - local).num_milliseconds(), Ordering::SeqCst);
+        self.measured.store(true, Ordering::SeqCst);
+    }
+
+    /// Last measured server time minus local time, `None` before the first response
+    pub fn skew(&self) -> Option<Duration> {
+        if self.measured.load(Ordering::SeqCst) {
+            Some(Duration::milliseconds(self.skew_ms.load(Ordering::SeqCst)))
+        } else {
+            None
+        }
+    }
+
+    /// Correction currently added to timestamp nonces
+    pub fn offset(&self) -> Duration {
+        Duration::milliseconds(self.offset_ms.load(Ordering::SeqCst))
+    }
+
+    /// Apply the measured skew to subsequent nonces, returns the new offset
+    ///
+    /// Random nonces do not depend on the clock, nothing is corrected and `None` is returned.
+    pub fn correct(&self) -> Option<Duration> {
+        if self.scheme != NonceScheme::Timestamp {
+            return None;
+        }
+        let skew = self.skew()?;
+        self.offset_ms
+            .store(skew.num_milliseconds(), Ordering::SeqCst);
+        Some(skew)
+    }
+}
+
+/// Phrases of the error messages rejecting a request for its nonce or timestamp, lowercase
+const NONCE_REJECTIONS: &[&str] = &[
+    "invalid nonce",
+    "nonce has already been used",
+    "nonce already used",
+    "nonce is too small",
+    "nonce too small",
+    "nonce must be greater",
+    "invalid timestamp",
+    "timestamp expired",
+    "timestamp is too old",
+    "timestamp out of range",
+];
+
+/// Whether an API error message reports an invalid nonce or timestamp
+///
+/// Only the known rejection messages match, other errors that mention a
+/// timestamp do not trigger a clock correction.
+pub fn is_nonce_rejection(message: &str) -> bool {
+    let message = message.to_lowercase();
+    NONCE_REJECTIONS
+        .iter()
+        .any(|rejection| message.contains(rejection))
+}
@@ -0,0 +1,128 @@
+use base64;
+use serde_json;
+use zeroize::Zeroize;
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use error::{Error, ErrorType};
+use query::Result;
+
+/// Environment variables read by `Credentials::from_env`
+pub const KEY_VAR: &str = "TRADESATOSHI_API_KEY";
+pub const SECRET_VAR: &str = "TRADESATOSHI_API_SECRET";
+
+#[derive(Deserialize)]
+struct CredentialsFile {
+    api_key: String,
+    api_secret: String,
+}
+
+/// API key and decoded secret, the secret is zeroed when dropped
+///
+/// `Debug` never shows the secret and only the start of the key.
+pub struct Credentials {
+    api_key: String,
+    secret: Vec<u8>,
+}
+
+impl Credentials {
+    /// api_secret: The base64 encoded secret, zeroed once decoded (required)
+    pub fn new(api_key: String, mut api_secret: String) -> Result<Self> {
+        let secret = base64::decode(api_secret.trim());
+        api_secret.zeroize();
+        match secret {
+            Ok(secret) => Ok(Credentials { api_key, secret }),
+            Err(_) => Err(Error {
+                error_type: ErrorType::InvalidCredentials,
+                message: "API secret is not valid base64".to_string(),
+            }),
+        }
+    }
+
+    /// Read `TRADESATOSHI_API_KEY` and `TRADESATOSHI_API_SECRET`
+    pub fn from_env() -> Result<Self> {
+        Credentials::from_env_vars(KEY_VAR, SECRET_VAR)
+    }
+
+    pub fn from_env_vars(key_var: &str, secret_var: &str) -> Result<Self> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| Error {
+                error_type: ErrorType::InvalidCredentials,
+                message: format!("{} is not set", name),
+            })
+        };
+        Credentials::new(var(key_var)?, var(secret_var)?)
+    }
+
+    /// Read a JSON file with `api_key` and `api_secret`
+    ///
+    /// On unix the file may not be readable by group or others.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        check_permissions(path, &file)?;
+        let contents: CredentialsFile = serde_json::from_reader(file)?;
+        Credentials::new(contents.api_key, contents.api_secret)
+    }
+
+    /// Load the key and base64 secret from e.g. a secret manager
+    pub fn from_provider<F>(provider: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<(String, String)>,
+    {
+        let (api_key, api_secret) = provider()?;
+        Credentials::new(api_key, api_secret)
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    /// Decoded secret used as HMAC key
+    pub(crate) fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+}
+
+/// Refuse files other users can read, they hold secrets
+#[cfg(unix)]
+pub(crate) fn check_permissions(path: &Path, file: &File) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = file.metadata()?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(Error {
+            error_type: ErrorType::InvalidCredentials,
+            message: format!(
+                "{} is accessible by other users (mode {:o}), run chmod 600 on it",
+                path.display(),
+                mode & 0o777
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn check_permissions(_path: &Path, _file: &File) -> Result<()> {
+    Ok(())
+}
+
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key: String = self.api_key.chars().take(4).collect();
+        f.debug_struct("Credentials")
+            .field("api_key", &format!("{}…", key))
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
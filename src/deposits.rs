@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use query::{Client, Result};
+use values::Transaction;
+
+#[derive(Debug, Clone)]
+pub enum DepositEvent {
+    Appeared {
+        id: String,
+        currency: String,
+        amount: f32,
+        confirmations: u32,
+        required: u32,
+    },
+    Confirmation {
+        id: String,
+        currency: String,
+        confirmations: u32,
+        required: u32,
+    },
+    /// The deposit is confirmed and shows up in the available balance
+    Credited {
+        id: String,
+        currency: String,
+        amount: f32,
+    },
+    /// No new confirmation for longer than the stuck timeout
+    ///
+    /// Also reported for a deposit that dropped off `get_deposits` before it was
+    /// credited, once it is older than the timeout. The deposit is not followed after that.
+    Stuck {
+        id: String,
+        currency: String,
+        confirmations: u32,
+        since: Duration,
+    },
+}
+
+#[derive(Clone)]
+struct TrackedDeposit {
+    currency: String,
+    amount: f32,
+    confirmations: u32,
+    last_progress: Instant,
+    /// Available balance when the deposit was first seen unconfirmed, None when it
+    /// was first seen confirmed and the balance may already include it
+    baseline: Option<f32>,
+    stuck: bool,
+}
+
+/// Polls `get_deposits` and follows every deposit until it is credited
+pub struct DepositWatcher {
+    /// Deposits not credited yet
+    deposits: HashMap<String, TrackedDeposit>,
+    /// Credited deposits still returned by `get_deposits`
+    credited: HashSet<String>,
+    min_confirmations: HashMap<String, u32>,
+    currency: Option<String>,
+    count: u32,
+    stuck_after: Duration,
+    primed: bool,
+}
+
+impl Default for DepositWatcher {
+    fn default() -> Self {
+        DepositWatcher::new()
+    }
+}
+
+impl DepositWatcher {
+    pub fn new() -> Self {
+        DepositWatcher {
+            deposits: HashMap::new(),
+            credited: HashSet::new(),
+            min_confirmations: HashMap::new(),
+            currency: None,
+            count: 20,
+            stuck_after: Duration::from_secs(60 * 60),
+            primed: false,
+        }
+    }
+
+    /// Only watch deposits of this currency e.g. 'BTC' (default: all)
+    pub fn currency(mut self, currency: &str) -> Self {
+        self.currency = Some(currency.to_string());
+        self
+    }
+
+    /// Deposits fetched on each poll (default: 20)
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Report deposits without a new confirmation for this long (default: 1h)
+    pub fn stuck_after(mut self, stuck_after: Duration) -> Self {
+        self.stuck_after = stuck_after;
+        self
+    }
+
+    /// Reload `Currency::min_confirmation` for all currencies
+    pub fn refresh_currencies(&mut self, client: &Client) -> Result<()> {
+        self.min_confirmations = client
+            .get_currencies()?
+            .into_iter()
+            .map(|currency| (currency.currency, currency.min_confirmation))
+            .collect();
+        Ok(())
+    }
+
+    /// Poll deposits, returns the events since the last poll
+    ///
+    /// Deposits that already look credited on the first poll are recorded without events.
+    /// Nothing is updated unless every request succeeds, so the events of a failed
+    /// poll are reported by the next one.
+    pub fn poll(&mut self, client: &Client) -> Result<Vec<DepositEvent>> {
+        if self.min_confirmations.is_empty() {
+            self.refresh_currencies(client)?;
+        }
+        let transactions = client.get_deposits(self.currency.clone(), Some(self.count))?;
+        self.poll_with(&transactions, |currency| {
+            Ok(client.get_balance(currency.to_string())?.available)
+        })
+    }
+
+    /// Like `poll` with the deposits fetched by the caller
+    ///
+    /// transactions: Latest deposits (required)
+    /// get_available: Fetches the available balance of a currency (required)
+    pub(crate) fn poll_with<F>(
+        &mut self,
+        transactions: &[Transaction],
+        mut get_available: F,
+    ) -> Result<Vec<DepositEvent>>
+    where
+        F: FnMut(&str) -> Result<f32>,
+    {
+        let mut available: HashMap<String, f32> = HashMap::new();
+        let mut balance = |currency: &str| -> Result<f32> {
+            if let Some(&available) = available.get(currency) {
+                return Ok(available);
+            }
+            let current = get_available(currency)?;
+            available.insert(currency.to_string(), current);
+            Ok(current)
+        };
+        let mut events = Vec::new();
+        let mut deposits = self.deposits.clone();
+        let mut credited = self.credited.clone();
+
+        for transaction in transactions {
+            if credited.contains(&transaction.id) {
+                continue;
+            }
+            let required = self
+                .min_confirmations
+                .get(&transaction.currency)
+                .cloned()
+                .unwrap_or(1);
+            let confirmed = transaction.confirmations >= required;
+
+            if !deposits.contains_key(&transaction.id) {
+                if !self.primed && confirmed && is_complete(&transaction.status) {
+                    credited.insert(transaction.id.clone());
+                    continue;
+                }
+                deposits.insert(
+                    transaction.id.clone(),
+                    TrackedDeposit {
+                        currency: transaction.currency.clone(),
+                        amount: transaction.amount,
+                        confirmations: transaction.confirmations,
+                        last_progress: Instant::now(),
+                        baseline: if confirmed {
+                            None
+                        } else {
+                            Some(balance(&transaction.currency)?)
+                        },
+                        stuck: false,
+                    },
+                );
+                events.push(DepositEvent::Appeared {
+                    id: transaction.id.clone(),
+                    currency: transaction.currency.clone(),
+                    amount: transaction.amount,
+                    confirmations: transaction.confirmations,
+                    required,
+                });
+            }
+
+            let deposit = deposits
+                .get_mut(&transaction.id)
+                .expect("Deposit should exist!");
+
+            if transaction.confirmations > deposit.confirmations {
+                deposit.confirmations = transaction.confirmations;
+                deposit.last_progress = Instant::now();
+                deposit.stuck = false;
+                events.push(DepositEvent::Confirmation {
+                    id: transaction.id.clone(),
+                    currency: deposit.currency.clone(),
+                    confirmations: transaction.confirmations,
+                    required,
+                });
+            }
+
+            if confirmed {
+                let increased = match deposit.baseline {
+                    Some(baseline) => {
+                        balance(&deposit.currency)? - baseline >= deposit.amount * 0.999
+                    }
+                    None => true,
+                };
+                if increased || is_complete(&transaction.status) {
+                    events.push(DepositEvent::Credited {
+                        id: transaction.id.clone(),
+                        currency: deposit.currency.clone(),
+                        amount: deposit.amount,
+                    });
+                    credited.insert(transaction.id.clone());
+                    continue;
+                }
+            }
+
+            let since = deposit.last_progress.elapsed();
+            if !deposit.stuck && since > self.stuck_after {
+                deposit.stuck = true;
+                events.push(DepositEvent::Stuck {
+                    id: transaction.id.clone(),
+                    currency: deposit.currency.clone(),
+                    confirmations: deposit.confirmations,
+                    since,
+                });
+            }
+        }
+
+        // Deposits no longer listed can only be given up on once they are old enough
+        let mut gone = Vec::new();
+        for (id, deposit) in &deposits {
+            if transactions.iter().any(|transaction| &transaction.id == id) {
+                continue;
+            }
+            let since = deposit.last_progress.elapsed();
+            if since > self.stuck_after {
+                if !deposit.stuck {
+                    events.push(DepositEvent::Stuck {
+                        id: id.clone(),
+                        currency: deposit.currency.clone(),
+                        confirmations: deposit.confirmations,
+                        since,
+                    });
+                }
+                gone.push(id.clone());
+            }
+        }
+
+        // Credited deposits are dropped, and only remembered while they are still listed
+        deposits.retain(|id, _| !credited.contains(id) && !gone.contains(id));
+        credited.retain(|id| transactions.iter().any(|transaction| &transaction.id == id));
+        self.deposits = deposits;
+        self.credited = credited;
+        self.primed = true;
+        Ok(events)
+    }
+}
+
+fn is_complete(status: &str) -> bool {
+    let status = status.to_lowercase();
+    (status.contains("complete") || status.contains("confirmed")) && !status.contains("unconfirmed")
+}
@@ -1,9 +1,19 @@
 use std::error::Error as StdError;
 use std::fmt;
+use std::io;
 
 use reqwest::Error as ReqwestError;
+#[cfg(feature = "metrics")]
+use prometheus;
+#[cfg(feature = "sqlite")]
+use rusqlite;
 use serde_json;
 
+use address::AddressError;
+use markets::ValidationError;
+use risk::RiskViolation;
+use withdraw::WithdrawRejection;
+
 #[derive(Debug)]
 pub struct Error {
     pub error_type: ErrorType,
@@ -15,6 +25,19 @@ pub enum ErrorType {
     APIError,
     JsonError,
     NoResults,
+    IoError,
+    ValidationError,
+    RiskError,
+    Frozen,
+    KillSwitchError,
+    WithdrawRejected,
+    InvalidAddress,
+    Timeout,
+    StoreError,
+    MetricsError,
+    InvalidCredentials,
+    NonceRejected,
+    Panicked,
 }
 
 impl StdError for Error {
@@ -23,6 +46,19 @@ impl StdError for Error {
             ErrorType::APIError => "Error while calling TradeSatoshi API",
             ErrorType::JsonError => "Error while converting response to JSON value",
             ErrorType::NoResults => "No results found",
+            ErrorType::IoError => "I/O error",
+            ErrorType::ValidationError => "Order failed validation",
+            ErrorType::RiskError => "Order rejected by risk checks",
+            ErrorType::Frozen => "Client is frozen",
+            ErrorType::KillSwitchError => "Kill switch could not confirm all orders are cancelled",
+            ErrorType::WithdrawRejected => "Withdrawal rejected by safeguards",
+            ErrorType::InvalidAddress => "Invalid address",
+            ErrorType::Timeout => "Timed out",
+            ErrorType::StoreError => "Local store error",
+            ErrorType::MetricsError => "Metrics error",
+            ErrorType::InvalidCredentials => "Invalid API credentials",
+            ErrorType::NonceRejected => "Request rejected for its nonce",
+            ErrorType::Panicked => "Worker thread panicked",
         }
     }
 }
@@ -30,9 +66,22 @@ impl StdError for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.error_type {
-            ErrorType::APIError => write!(f, "{}: {}", self, self.cause().unwrap()),
-            ErrorType::JsonError => write!(f, "{}: {}", self, self.cause().unwrap()),
-            ErrorType::NoResults => write!(f, "{} ({})!", self, self.cause().unwrap()),
+            ErrorType::APIError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::JsonError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::NoResults => write!(f, "{} ({})!", self.description(), self.message),
+            ErrorType::IoError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::ValidationError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::RiskError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::Frozen => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::KillSwitchError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::WithdrawRejected => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::InvalidAddress => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::Timeout => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::StoreError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::MetricsError => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::InvalidCredentials => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::NonceRejected => write!(f, "{}: {}", self.description(), self.message),
+            ErrorType::Panicked => write!(f, "{}: {}", self.description(), self.message),
         }
     }
 }
@@ -46,6 +95,71 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error {
+            error_type: ErrorType::IoError,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(error: ValidationError) -> Self {
+        Error {
+            error_type: ErrorType::ValidationError,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<RiskViolation> for Error {
+    fn from(error: RiskViolation) -> Self {
+        Error {
+            error_type: ErrorType::RiskError,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<WithdrawRejection> for Error {
+    fn from(error: WithdrawRejection) -> Self {
+        Error {
+            error_type: ErrorType::WithdrawRejected,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<AddressError> for Error {
+    fn from(error: AddressError) -> Self {
+        Error {
+            error_type: ErrorType::InvalidAddress,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error {
+            error_type: ErrorType::StoreError,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl From<prometheus::Error> for Error {
+    fn from(error: prometheus::Error) -> Self {
+        Error {
+            error_type: ErrorType::MetricsError,
+            message: error.to_string(),
+        }
+    }
+}
+
 impl From<ReqwestError> for Error {
     fn from(error: ReqwestError) -> Self {
         let mut err: Option<Error> = None;
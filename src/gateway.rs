@@ -0,0 +1,426 @@
+use serde::Serialize;
+use serde_json::{self, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing;
+use url::form_urlencoded;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use credentials::{check_permissions, Credentials};
+use error::{Error, ErrorType};
+use query::{Client, Result};
+
+/// Permission of a gateway token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Market data, balances, orders and history
+    Read,
+    /// Submitting and cancelling orders
+    Trade,
+    /// Withdrawals and transfers
+    Withdraw,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct TokenConfig {
+    /// Name of the calling service, used in the call log
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Gateway settings as stored in a JSON file
+#[derive(Deserialize)]
+struct GatewayFile {
+    #[serde(default = "default_listen")]
+    listen: String,
+    api_key: String,
+    api_secret: String,
+    #[serde(default)]
+    tokens: Vec<TokenConfig>,
+}
+
+/// Gateway settings, the API secret is only kept decoded in `Credentials`
+#[derive(Debug)]
+pub struct GatewayConfig {
+    /// Address to listen on (default: 127.0.0.1:8420)
+    pub listen: String,
+    pub credentials: Credentials,
+    pub tokens: Vec<TokenConfig>,
+}
+
+fn default_listen() -> String {
+    "127.0.0.1:8420".to_string()
+}
+
+impl GatewayConfig {
+    /// On unix the file may not be readable by group or others
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        check_permissions(path, &file)?;
+        let config: GatewayFile = serde_json::from_reader(file)?;
+        Ok(GatewayConfig {
+            listen: config.listen,
+            credentials: Credentials::new(config.api_key, config.api_secret)?,
+            tokens: config.tokens,
+        })
+    }
+}
+
+impl fmt::Debug for TokenConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TokenConfig")
+            .field("name", &self.name)
+            .field("token", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+pub(crate) struct Caller {
+    name: String,
+    token: String,
+    scopes: HashSet<Scope>,
+}
+
+/// Response status and JSON body in the shape of the exchange API
+pub(crate) type Reply = (u16, Value);
+
+/// Local HTTP server exposing the client to services holding a gateway token
+///
+/// Callers authenticate with `Authorization: Bearer <token>`. Reads are `GET`,
+/// everything that changes the account is `POST` with a JSON body.
+pub struct Gateway {
+    client: Client,
+    callers: Vec<Caller>,
+    workers: usize,
+}
+
+impl Gateway {
+    pub fn new(client: Client) -> Self {
+        Gateway {
+            client,
+            callers: Vec::new(),
+            workers: 4,
+        }
+    }
+
+    pub fn from_config(config: GatewayConfig) -> Self {
+        let client = Client::with_credentials(config.credentials);
+        config
+            .tokens
+            .iter()
+            .fold(Gateway::new(client), |gateway, token| {
+                gateway.token(&token.name, &token.token, &token.scopes)
+            })
+    }
+
+    /// Allow a service to call with `token` within `scopes`
+    pub fn token(mut self, name: &str, token: &str, scopes: &[Scope]) -> Self {
+        self.callers.push(Caller {
+            name: name.to_string(),
+            token: token.to_string(),
+            scopes: scopes.iter().cloned().collect(),
+        });
+        self
+    }
+
+    /// Threads handling requests (default: 4)
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Listen on `addr` e.g. '127.0.0.1:8420', blocks while serving
+    pub fn serve(self, addr: &str) -> Result<()> {
+        let server = Server::http(addr).map_err(|error| Error {
+            error_type: ErrorType::IoError,
+            message: format!("Could not listen on {}: {}", addr, error),
+        })?;
+        tracing::info!("Gateway listening on {}", addr);
+
+        let server = Arc::new(server);
+        let gateway = Arc::new(self);
+        let workers: Vec<_> = (0..gateway.workers)
+            .map(|_| {
+                let server = server.clone();
+                let gateway = gateway.clone();
+                thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        gateway.respond(request);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+
+    fn respond(&self, mut request: Request) {
+        let started = Instant::now();
+        let method = request.method().clone();
+        let url = request.url().to_string();
+
+        let caller = self.bearer(
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Authorization"))
+                .map(|header| header.value.as_str()),
+        );
+
+        // Bodies of unknown callers are not read
+        let (status, body) = match caller {
+            Some(_) => {
+                let mut body = String::new();
+                match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) => self.reply(caller, &method, &url, &body),
+                    Err(error) => failure(400, &error.to_string()),
+                }
+            }
+            None => self.reply(None, &method, &url, ""),
+        };
+
+        // Query strings are logged, bodies are not as they carry addresses and amounts
+        let name = caller.map_or("-", |caller| caller.name.as_str());
+        if status < 400 {
+            tracing::info!(
+                "{} {} {} {} {}ms",
+                name,
+                method,
+                url,
+                status,
+                started.elapsed().as_millis()
+            );
+        } else {
+            tracing::warn!(
+                "{} {} {} {} {}ms: {}",
+                name,
+                method,
+                url,
+                status,
+                started.elapsed().as_millis(),
+                body["message"]
+            );
+        }
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                    .expect("static header is valid"),
+            );
+        if let Err(error) = request.respond(response) {
+            tracing::warn!("{} {} {}: could not respond: {}", name, method, url, error);
+        }
+    }
+
+    /// Caller of an `Authorization: Bearer <token>` header value
+    pub(crate) fn bearer(&self, authorization: Option<&str>) -> Option<&Caller> {
+        authorization
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .and_then(|token| self.caller(token))
+    }
+
+    /// Reply to a request, 401 without a known caller
+    pub(crate) fn reply(
+        &self,
+        caller: Option<&Caller>,
+        method: &Method,
+        url: &str,
+        body: &str,
+    ) -> Reply {
+        let caller = match caller {
+            Some(caller) => caller,
+            None => return failure(401, "Missing or unknown token"),
+        };
+        let (path, query) = match url.find('?') {
+            Some(index) => (&url[..index], &url[index + 1..]),
+            None => (url, ""),
+        };
+        let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        self.handle(caller, method, path, &params, body)
+    }
+
+    /// Caller owning the token, compared in constant time
+    fn caller(&self, token: &str) -> Option<&Caller> {
+        let mut found = None;
+        for caller in &self.callers {
+            if constant_time_eq(caller.token.as_bytes(), token.as_bytes()) {
+                found = Some(caller);
+            }
+        }
+        found
+    }
+
+    fn handle(
+        &self,
+        caller: &Caller,
+        method: &Method,
+        path: &str,
+        params: &HashMap<String, String>,
+        body: &str,
+    ) -> Reply {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let scope = match (method, segments.as_slice()) {
+            (&Method::Get, _) => Scope::Read,
+            (&Method::Post, &["orders"]) | (&Method::Post, &["cancel"]) => Scope::Trade,
+            (&Method::Post, &["address"]) => Scope::Trade,
+            (&Method::Post, &["withdraw"]) | (&Method::Post, &["transfer"]) => Scope::Withdraw,
+            _ => return failure(404, "Unknown endpoint"),
+        };
+        if !caller.scopes.contains(&scope) {
+            return failure(403, &format!("Token lacks the {:?} scope", scope));
+        }
+
+        let result = match *method {
+            Method::Get => self.get(&segments, params),
+            _ => match serde_json::from_str::<Value>(if body.is_empty() { "{}" } else { body }) {
+                Ok(body) => self.post(&segments, &body),
+                Err(error) => return failure(400, &format!("Invalid JSON body: {}", error)),
+            },
+        };
+        match result {
+            Ok(Some(result)) => (200, json!({ "success": true, "result": result })),
+            Ok(None) => failure(404, "Unknown endpoint"),
+            Err(error) => match error.error_type {
+                ErrorType::ValidationError
+                | ErrorType::InvalidAddress
+                | ErrorType::Frozen
+                | ErrorType::RiskError
+                | ErrorType::WithdrawRejected => failure(400, &error.to_string()),
+                _ => failure(502, &error.to_string()),
+            },
+        }
+    }
+
+    fn get(&self, segments: &[&str], params: &HashMap<String, String>) -> Result<Option<Value>> {
+        let client = &self.client;
+        let param = |name: &str| params.get(name).cloned();
+        let value = match *segments {
+            ["currencies"] => to_value(client.get_currencies()?)?,
+            ["ticker", market] => to_value(client.get_ticker(market.to_string())?)?,
+            ["history", market] => to_value(
+                client.get_market_history(market.to_string(), parse_param(params, "count")?)?,
+            )?,
+            ["summaries"] => to_value(client.get_market_summaries()?)?,
+            ["summary", market] => to_value(client.get_market_summary(market.to_string())?)?,
+            ["book", market] => to_value(client.get_order_book(
+                market.to_string(),
+                param("type"),
+                parse_param(params, "depth")?,
+            )?)?,
+            ["balances"] => to_value(client.get_balances()?)?,
+            ["balance", currency] => to_value(client.get_balance(currency.to_string())?)?,
+            ["orders"] => {
+                to_value(client.get_orders(param("market"), parse_param(params, "count")?)?)?
+            }
+            ["orders", id] => to_value(client.get_order(parse_value("id", id)?)?)?,
+            ["trades"] => to_value(client.get_trade_history(
+                param("market"),
+                parse_param(params, "count")?,
+                parse_param(params, "page")?,
+            )?)?,
+            ["deposits"] => {
+                to_value(client.get_deposits(param("currency"), parse_param(params, "count")?)?)?
+            }
+            ["withdrawals"] => {
+                to_value(client.get_withdrawals(param("currency"), parse_param(params, "count")?)?)?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    fn post(&self, segments: &[&str], body: &Value) -> Result<Option<Value>> {
+        let client = &self.client;
+        let value = match *segments {
+            ["orders"] => to_value(client.submit_order(
+                field(body, "market")?,
+                field(body, "type")?,
+                number(body, "amount")?,
+                number(body, "price")?,
+            )?)?,
+            ["cancel"] => to_value(client.cancel_order(
+                field(body, "type")?,
+                body["orderId"].as_u64().map(|id| id as u32),
+                body["market"].as_str().map(|market| market.to_string()),
+            )?)?,
+            ["address"] => to_value(client.generate_address(field(body, "currency")?)?)?,
+            ["withdraw"] => to_value(client.submit_withdraw(
+                field(body, "currency")?,
+                field(body, "address")?,
+                number(body, "amount")?,
+            )?)?,
+            ["transfer"] => to_value(client.submit_transfer(
+                field(body, "currency")?,
+                field(body, "username")?,
+                number(body, "amount")?,
+            )?)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+fn failure(status: u16, message: &str) -> Reply {
+    (status, json!({ "success": false, "message": message }))
+}
+
+fn invalid(message: String) -> Error {
+    Error {
+        error_type: ErrorType::ValidationError,
+        message,
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("Invalid {}: {}", name, value)))
+}
+
+fn parse_param<T: FromStr>(params: &HashMap<String, String>, name: &str) -> Result<Option<T>> {
+    params
+        .get(name)
+        .map(|value| parse_value(name, value))
+        .transpose()
+}
+
+fn field(body: &Value, name: &str) -> Result<String> {
+    body[name]
+        .as_str()
+        .map(|value| value.to_string())
+        .ok_or_else(|| invalid(format!("Missing {}", name)))
+}
+
+fn number(body: &Value, name: &str) -> Result<f32> {
+    body[name]
+        .as_f64()
+        .map(|value| value as f32)
+        .ok_or_else(|| invalid(format!("Missing {}", name)))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
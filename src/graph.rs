@@ -0,0 +1,148 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use markets::split_market;
+use query::{Client, Result};
+use values::*;
+
+/// Conversion of `from` into `to` on a market
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub market: String,
+    pub from: String,
+    pub to: String,
+    /// `from` is the base currency, the conversion sells on the market
+    pub sell: bool,
+}
+
+/// Graph of currencies connected by markets
+pub struct MarketGraph {
+    summaries: HashMap<String, MarketSummary>,
+    edges: HashMap<String, Vec<Edge>>,
+}
+
+impl MarketGraph {
+    pub fn new(summaries: Vec<MarketSummary>) -> Self {
+        let mut edges: HashMap<String, Vec<Edge>> = HashMap::new();
+        for summary in &summaries {
+            let (base, quote) = match split_market(&summary.market) {
+                Some(currencies) => currencies,
+                None => continue,
+            };
+            edges.entry(base.to_string()).or_default().push(Edge {
+                market: summary.market.clone(),
+                from: base.to_string(),
+                to: quote.to_string(),
+                sell: true,
+            });
+            edges.entry(quote.to_string()).or_default().push(Edge {
+                market: summary.market.clone(),
+                from: quote.to_string(),
+                to: base.to_string(),
+                sell: false,
+            });
+        }
+        MarketGraph {
+            summaries: summaries
+                .into_iter()
+                .map(|summary| (summary.market.clone(), summary))
+                .collect(),
+            edges,
+        }
+    }
+
+    /// Build the graph from `get_market_summaries`
+    pub fn from_client(client: &Client) -> Result<Self> {
+        Ok(MarketGraph::new(client.get_market_summaries()?))
+    }
+
+    pub fn summary(&self, market: &str) -> Option<&MarketSummary> {
+        self.summaries.get(market)
+    }
+
+    pub fn currencies(&self) -> Vec<&str> {
+        self.edges.keys().map(|currency| currency.as_str()).collect()
+    }
+
+    /// Conversions starting at a currency
+    pub fn edges(&self, currency: &str) -> &[Edge] {
+        self.edges
+            .get(currency)
+            .map(|edges| edges.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Path with the fewest hops, empty when `from` and `to` are the same currency
+    pub fn shortest_path(&self, from: &str, to: &str, max_hops: usize) -> Option<Vec<Edge>> {
+        self.shortest_path_by(from, to, max_hops, |_| true)
+    }
+
+    /// Path with the fewest hops using only the edges `usable` accepts
+    pub fn shortest_path_by<F>(
+        &self,
+        from: &str,
+        to: &str,
+        max_hops: usize,
+        usable: F,
+    ) -> Option<Vec<Edge>>
+    where
+        F: Fn(&Edge) -> bool,
+    {
+        if from == to {
+            return Some(Vec::new());
+        }
+        let mut visited: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<(&str, Vec<Edge>)> = VecDeque::new();
+        visited.insert(from);
+        queue.push_back((from, Vec::new()));
+        while let Some((currency, path)) = queue.pop_front() {
+            if path.len() >= max_hops {
+                continue;
+            }
+            for edge in self.edges(currency) {
+                if !usable(edge) || !visited.insert(&edge.to) {
+                    continue;
+                }
+                let mut next = path.clone();
+                next.push(edge.clone());
+                if edge.to == to {
+                    return Some(next);
+                }
+                queue.push_back((&edge.to, next));
+            }
+        }
+        None
+    }
+
+    /// All paths from `from` to `to` with at most `max_hops` that visit no currency twice
+    pub fn paths(&self, from: &str, to: &str, max_hops: usize) -> Vec<Vec<Edge>> {
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+        self.collect_paths(from, to, max_hops, &mut path, &mut paths);
+        paths
+    }
+
+    fn collect_paths(
+        &self,
+        currency: &str,
+        to: &str,
+        max_hops: usize,
+        path: &mut Vec<Edge>,
+        paths: &mut Vec<Vec<Edge>>,
+    ) {
+        if path.len() >= max_hops {
+            return;
+        }
+        for edge in self.edges(currency) {
+            if edge.to != to && path.iter().any(|hop| hop.from == edge.to) {
+                continue;
+            }
+            path.push(edge.clone());
+            if edge.to == to {
+                paths.push(path.clone());
+            } else {
+                self.collect_paths(&edge.to, to, max_hops, path, paths);
+            }
+            path.pop();
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use signal_hook;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use error::{Error, ErrorType};
+use query::{Client, Result};
+
+/// Cancels all orders and freezes the client
+#[derive(Clone)]
+pub struct KillSwitch {
+    client: Arc<Client>,
+    attempts: u32,
+    retry_delay: Duration,
+    poll_interval: Duration,
+}
+
+impl KillSwitch {
+    pub fn new(client: Arc<Client>) -> Self {
+        KillSwitch {
+            client,
+            attempts: 10,
+            retry_delay: Duration::from_secs(2),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Max cancel attempts before giving up (default: 10)
+    ///
+    /// The cap is deliberate, so a dead API does not block the caller forever. Once it
+    /// is reached `trigger` returns a `KillSwitchError` and the client stays frozen.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Time between cancel attempts (default: 2s)
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// How often signal and file watchers check their trigger (default: 1s)
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Freeze the client, cancel all orders and retry until no order is open
+    ///
+    /// Gives up after `attempts` with a `KillSwitchError` holding the last problem.
+    /// The client stays frozen even if cancelling could not be confirmed.
+    pub fn trigger(&self) -> Result<()> {
+        self.client.freeze();
+        let client = &self.client;
+        self.trigger_with(
+            || {
+                client
+                    .cancel_order("All".to_string(), None, None)
+                    .map(|_| ())
+            },
+            || {
+                client
+                    .get_orders(None, Some(100))
+                    .map(|orders| orders.len())
+            },
+        )
+    }
+
+    /// Cancel and confirm with the given requests, see `trigger`
+    ///
+    /// cancel: Cancels all orders (required)
+    /// open: Count of orders still open (required)
+    pub(crate) fn trigger_with<C, O>(&self, mut cancel: C, mut open: O) -> Result<()>
+    where
+        C: FnMut() -> Result<()>,
+        O: FnMut() -> Result<usize>,
+    {
+        let mut last_error = String::new();
+        for attempt in 0..self.attempts {
+            if attempt > 0 {
+                thread::sleep(self.retry_delay);
+            }
+            if let Err(error) = cancel() {
+                last_error = error.to_string();
+                continue;
+            }
+            match open() {
+                Ok(0) => return Ok(()),
+                Ok(count) => last_error = format!("{} orders still open", count),
+                Err(error) => last_error = error.to_string(),
+            }
+        }
+        Err(Error {
+            error_type: ErrorType::KillSwitchError,
+            message: format!(
+                "Cancelling not confirmed after {} attempts: {}",
+                self.attempts, last_error
+            ),
+        })
+    }
+
+    /// Allow trading and withdrawals again
+    pub fn rearm(&self) {
+        self.client.rearm();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.client.is_frozen()
+    }
+
+    /// Trigger once the process receives the signal e.g. `signal_hook::SIGUSR1`
+    pub fn watch_signal(&self, signal: i32) -> Result<Watcher> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let id = signal_hook::flag::register(signal, Arc::clone(&flag))?;
+        let mut watcher = self.watch(move || flag.load(Ordering::SeqCst));
+        watcher.signal = Some(id);
+        Ok(watcher)
+    }
+
+    /// Trigger once the file exists
+    pub fn watch_file<P: Into<PathBuf>>(&self, path: P) -> Watcher {
+        let path = path.into();
+        self.watch(move || path.exists())
+    }
+
+    fn watch<F>(&self, fired: F) -> Watcher
+    where
+        F: Fn() -> bool + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let switch = self.clone();
+        let thread = thread::spawn(move || loop {
+            if stopped.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+            if fired() {
+                return switch.trigger().map(|_| true);
+            }
+            thread::park_timeout(switch.poll_interval);
+        });
+        Watcher {
+            stop,
+            signal: None,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Watcher thread of a kill switch trigger, stops watching when dropped
+///
+/// Dropping does not wait for a trigger in progress, `stop` does.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    signal: Option<signal_hook::SigId>,
+    thread: Option<JoinHandle<Result<bool>>>,
+}
+
+impl Watcher {
+    /// Wait until the switch was triggered, returns the result of `trigger`
+    pub fn join(mut self) -> Result<()> {
+        self.wait().map(|_| ())
+    }
+
+    /// Stop watching, returns whether the switch was triggered before
+    pub fn stop(mut self) -> Result<bool> {
+        self.release();
+        self.wait()
+    }
+
+    fn wait(&mut self) -> Result<bool> {
+        let thread = self.thread.take().expect("Watcher thread should exist!");
+        thread.join().unwrap_or_else(|_| {
+            Err(Error {
+                error_type: ErrorType::Panicked,
+                message: "Kill switch watcher panicked".to_string(),
+            })
+        })
+    }
+
+    /// Unregister the signal and tell the thread to stop
+    fn release(&mut self) {
+        if let Some(id) = self.signal.take() {
+            signal_hook::unregister(id);
+        }
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(ref thread) = self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
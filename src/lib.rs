@@ -1,5 +1,6 @@
 extern crate reqwest;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
@@ -13,8 +14,46 @@ extern crate url;
 #[macro_use]
 extern crate strum_macros;
 extern crate rand;
+extern crate chrono;
+extern crate signal_hook;
+extern crate tiny_keccak;
+extern crate tracing;
+extern crate zeroize;
+#[cfg(feature = "metrics")]
+extern crate prometheus;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(any(feature = "gateway", feature = "metrics"))]
+extern crate tiny_http;
+pub mod accounts;
+pub mod address;
+pub mod arbitrage;
+pub mod cache;
+pub mod clock;
+pub mod credentials;
+pub mod deposits;
 pub mod error;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod graph;
+pub mod killswitch;
+pub mod markets;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod orders;
+pub mod pnl;
+pub mod ratelimit;
+pub mod risk;
+pub mod routing;
+pub mod runtime;
+pub mod signing;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod tax;
+pub mod valuation;
 pub mod values;
+pub mod withdraw;
+pub mod withdrawals;
 
 mod query;
 pub use query::Client;
@@ -81,4 +120,1929 @@ mod tests {
 
     // #[test]
     // fn test_submit_transfer() {}
+
+    ///////////////////////////
+    // Request signing tests //
+    ///////////////////////////
+
+    // Expected values computed independently with Python's hmac and urllib
+    const API_KEY: &str = "2b9e3a1f6c7d4e8fa0b1c2d3e4f50617";
+    const API_SECRET: &str = "dHJhZGVzYXRvc2hpLXRlc3Qtc2VjcmV0LTAxMjM0NTY3ODk=";
+
+    fn credentials() -> credentials::Credentials {
+        credentials::Credentials::new(API_KEY.to_string(), API_SECRET.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_string_to_sign() {
+        assert_eq!(
+            signing::string_to_sign(
+                API_KEY,
+                "https://tradesatoshi.com/api/private/getbalance",
+                r#"{"Currency":"BTC"}"#,
+                "1552521200",
+            ),
+            "2b9e3a1f6c7d4e8fa0b1c2d3e4f50617POST\
+             https%3a%2f%2ftradesatoshi.com%2fapi%2fprivate%2fgetbalance\
+             1552521200eyJDdXJyZW5jeSI6IkJUQyJ9"
+        );
+    }
+
+    #[test]
+    fn test_sign_request() {
+        let authorization = signing::sign_request(
+            &credentials(),
+            "https://tradesatoshi.com/api/private/getbalance",
+            r#"{"Currency":"BTC"}"#,
+            "1552521200",
+        );
+        assert_eq!(
+            authorization.to_string(),
+            "Basic 2b9e3a1f6c7d4e8fa0b1c2d3e4f50617:\
+             Ttv0z87byYfm5vmBlXf3n8J2rNVqw77Yt4mHzhrAgzYQrkW0W+1VhgrgirPkouJR2PwzYW/ogui1Wp6lT5ILvQ==:\
+             1552521200"
+        );
+    }
+
+    #[test]
+    fn test_sign_request_empty_params() {
+        let authorization = signing::sign_request(
+            &credentials(),
+            "https://tradesatoshi.com/api/private/getbalances",
+            "{}",
+            "1552521201",
+        );
+        assert_eq!(authorization.api_key, API_KEY);
+        assert_eq!(
+            authorization.signature,
+            "UqSeVRQr4jx2On1OS2EmsVL2h0zN3kwDVxw7RrXYasMLaocHxrapl+SJu2l4UM18jrjGP0WAF2Wbw7impCU8Jg=="
+        );
+        assert_eq!(authorization.nonce, "1552521201");
+    }
+
+    /////////////////////////
+    // Capital gains tests //
+    /////////////////////////
+
+    fn trade(
+        id: u32,
+        market: &str,
+        typeo: &str,
+        amount: f32,
+        rate: f32,
+        fee: f32,
+    ) -> values::TradeHistory {
+        values::TradeHistory {
+            id,
+            market: market.to_string(),
+            typeo: typeo.to_string(),
+            amount,
+            rate,
+            fee,
+            total: amount * rate,
+            time_stamp: format!("2019-03-{:02}T12:00:00", id),
+            is_api: true,
+        }
+    }
+
+    fn no_price(_: &str, _: chrono::DateTime<chrono::Utc>) -> Option<f32> {
+        None
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_tax_fifo() {
+        let trades = [
+            trade(1, "LTC_USD", "Buy", 1.0, 100.0, 0.0),
+            trade(2, "LTC_USD", "Buy", 1.0, 200.0, 0.0),
+            trade(3, "LTC_USD", "Sell", 1.5, 300.0, 0.0),
+        ];
+        let report = tax::CapitalGains::new("USD").report(&trades, &[], &[], no_price);
+        assert_eq!(report.lines.len(), 2);
+        assert_eq!(report.lines[0].lot_id, Some("trade:1".to_string()));
+        assert_close(report.lines[0].amount, 1.0);
+        assert_close(report.lines[0].gain.unwrap(), 200.0);
+        assert_eq!(report.lines[1].lot_id, Some("trade:2".to_string()));
+        assert_close(report.lines[1].amount, 0.5);
+        assert_close(report.lines[1].gain.unwrap(), 50.0);
+        assert_close(report.total_gain(), 250.0);
+        assert_eq!(report.open_lots.len(), 1);
+        assert_close(report.open_lots[0].amount, 0.5);
+    }
+
+    #[test]
+    fn test_tax_specific_id_falls_back_to_fifo() {
+        let trades = [
+            trade(1, "LTC_USD", "Buy", 1.0, 100.0, 0.0),
+            trade(2, "LTC_USD", "Buy", 1.0, 200.0, 0.0),
+            trade(3, "LTC_USD", "Sell", 1.5, 300.0, 0.0),
+        ];
+        let mut selections = std::collections::HashMap::new();
+        selections.insert(
+            "trade:3".to_string(),
+            vec![("trade:2".to_string(), 0.5), ("trade:9".to_string(), 1.0)],
+        );
+        let report = tax::CapitalGains::new("USD")
+            .matching(tax::LotMatching::SpecificId(selections))
+            .report(&trades, &[], &[], no_price);
+        assert_eq!(report.lines.len(), 2);
+        assert_eq!(report.lines[0].lot_id, Some("trade:2".to_string()));
+        assert_close(report.lines[0].amount, 0.5);
+        assert_close(report.lines[0].cost_basis, 100.0);
+        assert_eq!(report.lines[1].lot_id, Some("trade:1".to_string()));
+        assert_close(report.lines[1].amount, 1.0);
+        assert_close(report.lines[1].cost_basis, 100.0);
+        assert_eq!(report.open_lots[0].id, "trade:2");
+        assert_close(report.open_lots[0].amount, 0.5);
+    }
+
+    #[test]
+    fn test_tax_fees() {
+        let trades = [
+            trade(1, "LTC_USD", "Buy", 1.0, 100.0, 1.0),
+            trade(2, "LTC_USD", "Sell", 1.0, 150.0, 2.0),
+        ];
+        let report = tax::CapitalGains::new("USD").report(&trades, &[], &[], no_price);
+        assert_eq!(report.lines.len(), 1);
+        assert_close(report.lines[0].cost_basis, 101.0);
+        assert_close(report.lines[0].proceeds.unwrap(), 148.0);
+        assert_close(report.lines[0].gain.unwrap(), 47.0);
+    }
+
+    #[test]
+    fn test_tax_unpriced_disposal() {
+        let trades = [
+            trade(1, "LTC_USD", "Buy", 1.0, 100.0, 0.0),
+            trade(2, "LTC_BTC", "Sell", 1.0, 0.01, 0.0),
+        ];
+        let report = tax::CapitalGains::new("USD").report(&trades, &[], &[], no_price);
+        assert_eq!(report.lines.len(), 1);
+        assert_eq!(report.lines[0].proceeds, None);
+        assert_eq!(report.lines[0].gain, None);
+        assert_close(report.lines[0].cost_basis, 100.0);
+        assert!(report.unpriced.contains(&"trade:2".to_string()));
+        assert!(report.open_lots.iter().all(|lot| lot.currency != "LTC"));
+    }
+
+    #[test]
+    fn test_tax_csv_field() {
+        assert_eq!(tax::csv_field("trade:1"), "trade:1");
+        assert_eq!(tax::csv_field("a,b"), "\"a,b\"");
+        assert_eq!(tax::csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(tax::csv_field("a\nb"), "\"a\nb\"");
+    }
+
+    //////////////////////////////
+    // Address validation tests //
+    //////////////////////////////
+
+    fn check(
+        currency: &str,
+        address: &str,
+    ) -> Result<address::AddressCheck, address::AddressError> {
+        address::validate_address(currency, address)
+    }
+
+    #[test]
+    fn test_base58check_addresses() {
+        assert_eq!(
+            check("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpyT"),
+            Ok(address::AddressCheck::Valid)
+        );
+        assert_eq!(
+            check("BTC", "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"),
+            Ok(address::AddressCheck::Valid)
+        );
+        match check("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpyU") {
+            Err(address::AddressError::BadChecksum { .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match check("DOGE", "1BoatSLRHtKNngkdXEeobR76b53LETtpyT") {
+            Err(address::AddressError::UnknownVersion { version: 0, .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(check("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpy0").is_err());
+    }
+
+    // BIP-173 and BIP-350 test vectors
+    #[test]
+    fn test_segwit_addresses() {
+        for valid in &[
+            "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+            "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+            "BC1SW50QGDZ25J",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+        ] {
+            assert_eq!(
+                check("BTC", valid),
+                Ok(address::AddressCheck::Valid),
+                "{}",
+                valid
+            );
+        }
+        for invalid in &[
+            // Invalid checksum
+            "bc1zw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+            // Invalid witness version
+            "BC13W508D6QEJXTDG4Y5R3ZARVARY0C5XW7KN40WF2",
+            // Invalid program length
+            "bc1rw5uspcuh",
+            "BC1QR508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+            // Mixed case
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sL5k7",
+            // Bech32 instead of bech32m for version 1 and 16
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL",
+            // Bech32m instead of bech32 for version 0
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+        ] {
+            assert!(check("BTC", invalid).is_err(), "{}", invalid);
+        }
+        for testnet in &[
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+            "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+        ] {
+            assert_eq!(
+                check("BTC", testnet),
+                Err(address::AddressError::WrongNetwork {
+                    currency: "BTC".to_string(),
+                    prefix: "tb".to_string(),
+                })
+            );
+        }
+        match check(
+            "LTC",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+        ) {
+            Err(address::AddressError::WrongNetwork { ref prefix, .. }) if prefix == "bc" => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    // EIP-55 test vectors
+    #[test]
+    fn test_hex_addresses() {
+        for valid in &[
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+            "0xde709f2102306220921060314715629080e2fb77",
+            "0x27b1fdb04752bbc536007a920d24acb045561c26",
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert_eq!(
+                check("ETH", valid),
+                Ok(address::AddressCheck::Valid),
+                "{}",
+                valid
+            );
+        }
+        match check("ETH", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD") {
+            Err(address::AddressError::BadChecksum { .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(check("ETH", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+        assert_eq!(
+            check("XYZ", "anything"),
+            Ok(address::AddressCheck::Unchecked)
+        );
+    }
+
+    #[test]
+    fn test_address_error_masked() {
+        let error = check("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpyU").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("1BoatSLRHtKNngkdXEeobR76b53LETtpyU"));
+        assert_eq!(error.masked(), "Checksum of BTC address *** does not match");
+        let error = check("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpy0").unwrap_err();
+        assert!(!error
+            .masked()
+            .contains("1BoatSLRHtKNngkdXEeobR76b53LETtpy0"));
+    }
+
+    ////////////////////////////
+    // Withdrawal guard tests //
+    ////////////////////////////
+
+    const ALLOWED: &str = "1BoatSLRHtKNngkdXEeobR76b53LETtpyT";
+
+    fn rejection<T>(result: query::Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("not rejected"),
+            Err(error) => {
+                assert!(matches!(
+                    error.error_type,
+                    error::ErrorType::WithdrawRejected
+                ));
+                error.message
+            }
+        }
+    }
+
+    #[test]
+    fn test_withdraw_allowlist() {
+        use std::time::Duration;
+
+        let client = Client::with_credentials(credentials());
+        let mut guard = withdraw::WithdrawGuard::new(&client);
+        assert!(guard
+            .allow_address("BTC", "1BoatSLRHtKNngkdXEeobR76b53LETtpyU")
+            .is_err());
+        guard.allow_address("BTC", ALLOWED).unwrap();
+        guard.allow_username("alice");
+        assert!(rejection(guard.prepare_withdraw("BTC", ALLOWED, 1.0))
+            .starts_with("Allowlist entry is cooling down for "));
+        assert!(rejection(guard.prepare_transfer("BTC", "alice", 1.0))
+            .starts_with("Allowlist entry is cooling down for "));
+
+        let mut guard = guard.cooldown(Duration::from_secs(0));
+        assert!(guard.prepare_withdraw("BTC", ALLOWED, 1.0).is_ok());
+        assert!(guard.prepare_transfer("BTC", "alice", 1.0).is_ok());
+        assert_eq!(
+            rejection(guard.prepare_withdraw("BTC", "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", 1.0)),
+            "Address 3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy is not allowed for BTC"
+        );
+        assert_eq!(
+            rejection(guard.prepare_withdraw("LTC", ALLOWED, 1.0)),
+            format!("Address {} is not allowed for LTC", ALLOWED)
+        );
+        assert_eq!(
+            rejection(guard.prepare_transfer("BTC", "bob", 1.0)),
+            "Username bob is not allowed"
+        );
+
+        // Removing a destination drops its pending actions
+        assert_eq!(guard.pending().len(), 2);
+        guard.remove_address("BTC", ALLOWED);
+        guard.remove_username("alice");
+        assert!(guard.pending().is_empty());
+    }
+
+    #[test]
+    fn test_withdraw_limits() {
+        use std::time::{Duration, SystemTime};
+
+        let client = Client::with_credentials(credentials());
+        let mut guard = withdraw::WithdrawGuard::new(&client).cooldown(Duration::from_secs(0));
+        guard.allow_address("BTC", ALLOWED).unwrap();
+        guard.set_limits(
+            "BTC",
+            withdraw::WithdrawLimits {
+                per_transaction: Some(1.0),
+                per_day: Some(2.0),
+            },
+        );
+        assert_eq!(
+            rejection(guard.prepare_withdraw("BTC", ALLOWED, 1.5)),
+            "Amount 1.5 exceeds per transaction limit 1"
+        );
+        assert_eq!(
+            rejection(guard.prepare_withdraw("BTC", ALLOWED, 0.0)),
+            "Invalid amount 0"
+        );
+
+        // Only the last day of the same currency counts
+        let now = SystemTime::now();
+        guard.record_executed(now - Duration::from_secs(2 * 24 * 60 * 60), "BTC", 1.0);
+        guard.record_executed(now - Duration::from_secs(60 * 60), "BTC", 1.0);
+        guard.record_executed(now, "LTC", 5.0);
+        let token = guard.prepare_withdraw("BTC", ALLOWED, 1.0).unwrap();
+
+        // Limits are checked again on confirmation
+        guard.record_executed(now, "BTC", 0.5);
+        assert_eq!(
+            rejection(guard.confirm_withdraw(&token.id)),
+            "Daily total 2.5 would exceed limit 2"
+        );
+        assert_eq!(
+            rejection(guard.prepare_withdraw("BTC", ALLOWED, 1.0)),
+            "Daily total 2.5 would exceed limit 2"
+        );
+    }
+
+    #[test]
+    fn test_withdraw_tokens() {
+        use std::time::Duration;
+
+        let client = Client::with_credentials(credentials());
+        let mut guard = withdraw::WithdrawGuard::new(&client).cooldown(Duration::from_secs(0));
+        guard.allow_address("BTC", ALLOWED).unwrap();
+        guard.allow_username("alice");
+
+        let token = guard.prepare_transfer("BTC", "alice", 1.0).unwrap();
+        assert_eq!(
+            rejection(guard.confirm_withdraw(&token.id)),
+            format!("Token {} is for a different action", token.id)
+        );
+        assert_eq!(guard.pending().len(), 1);
+        assert!(guard.cancel(&token.id).is_some());
+        assert_eq!(
+            rejection(guard.confirm_transfer(&token.id)),
+            format!("Unknown token {}", token.id)
+        );
+
+        let mut guard = guard.token_ttl(Duration::from_secs(0));
+        let token = guard.prepare_withdraw("BTC", ALLOWED, 1.0).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(guard.pending().is_empty());
+        assert_eq!(
+            rejection(guard.confirm_withdraw(&token.id)),
+            format!("Token {} expired", token.id)
+        );
+    }
+
+    ///////////////////////
+    // Risk limits tests //
+    ///////////////////////
+
+    fn limits() -> risk::RiskLimits {
+        risk::RiskLimits::from_json(
+            r#"{
+                "max_order_notional": {"BTC": 0.5},
+                "max_open_orders_per_market": 3,
+                "max_position": {"LTC": 10.0},
+                "price_collar_pct": 5.0,
+                "daily_loss_limit": {"BTC": 0.1}
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_risk_notional() {
+        let limits = limits();
+        assert_eq!(limits.check_notional("BTC", 0.5), None);
+        assert_eq!(
+            limits.check_notional("BTC", 0.6),
+            Some(risk::RiskViolation::MaxOrderNotional {
+                notional: 0.6,
+                limit: 0.5,
+            })
+        );
+        assert_eq!(limits.check_notional("USDT", 1000.0), None);
+    }
+
+    #[test]
+    fn test_risk_open_orders() {
+        let limits = limits();
+        assert_eq!(limits.check_open_orders(2), None);
+        assert_eq!(
+            limits.check_open_orders(3),
+            Some(risk::RiskViolation::MaxOpenOrders { open: 3, limit: 3 })
+        );
+        assert_eq!(risk::RiskLimits::default().check_open_orders(100), None);
+    }
+
+    #[test]
+    fn test_risk_position() {
+        let limits = limits();
+        assert_eq!(limits.check_position("LTC", 10.0), None);
+        assert_eq!(
+            limits.check_position("LTC", 10.5),
+            Some(risk::RiskViolation::MaxPosition {
+                currency: "LTC".to_string(),
+                position: 10.5,
+                limit: 10.0,
+            })
+        );
+        assert_eq!(limits.check_position("BTC", 100.0), None);
+    }
+
+    #[test]
+    fn test_risk_price_collar() {
+        let limits = limits();
+        assert_eq!(limits.check_price_collar(104.0, 100.0), None);
+        assert_eq!(limits.check_price_collar(96.0, 100.0), None);
+        assert_eq!(
+            limits.check_price_collar(94.0, 100.0),
+            Some(risk::RiskViolation::PriceCollar {
+                price: 94.0,
+                last: 100.0,
+                limit_pct: 5.0,
+            })
+        );
+        // No last price to compare with
+        assert_eq!(limits.check_price_collar(94.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_risk_daily_loss() {
+        let limits = limits();
+        assert_eq!(limits.check_daily_loss("BTC", -0.1), None);
+        assert_eq!(limits.check_daily_loss("BTC", 5.0), None);
+        assert_eq!(
+            limits.check_daily_loss("BTC", -0.2),
+            Some(risk::RiskViolation::DailyLossLimit {
+                currency: "BTC".to_string(),
+                loss: 0.2,
+                limit: 0.1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_risk_trade_flows_any_order() {
+        let trades = [
+            trade(3, "LTC_BTC", "Sell", 1.0, 0.02, 0.0),
+            trade(1, "LTC_BTC", "Buy", 5.0, 0.01, 0.0),
+            trade(2, "LTC_BTC", "Buy", 2.0, 0.01, 0.001),
+            trade(4, "DOGE_BTC", "Buy", 100.0, 0.0001, 0.0),
+        ];
+        let since = values::parse_timestamp("2019-03-02T00:00:00").unwrap();
+        let flows = risk::trade_flows(&trades, since);
+        let ltc = flows["LTC_BTC"];
+        assert_close(ltc.0, 0.02 - 0.021);
+        assert_close(ltc.1, 1.0);
+        let doge = flows["DOGE_BTC"];
+        assert_close(doge.0, -0.01);
+        assert_close(doge.1, 100.0);
+    }
+
+    /// Pages of `page_size` trades of 250, newest first, the page number is ignored unless `pages`
+    fn history_page(
+        page: u32,
+        page_size: u32,
+        pages: bool,
+    ) -> query::Result<Vec<values::TradeHistory>> {
+        let start = if pages { page * page_size } else { 0 };
+        Ok((start..(start + page_size).min(250))
+            .map(|index| trade(250 - index, "LTC_BTC", "Buy", 1.0, 0.01, 0.0))
+            .collect())
+    }
+
+    #[test]
+    fn test_page_trade_history() {
+        let mut fetched = 0;
+        let trades = query::page_trade_history(
+            100,
+            |page| {
+                fetched += 1;
+                history_page(page, 100, true)
+            },
+            |_| false,
+        )
+        .unwrap();
+        assert_eq!((trades.len(), fetched), (250, 3));
+
+        // The same page again stops paging, its trades are not repeated
+        let mut fetched = 0;
+        let trades = query::page_trade_history(
+            100,
+            |page| {
+                fetched += 1;
+                history_page(page, 100, false)
+            },
+            |_| false,
+        )
+        .unwrap();
+        assert_eq!((trades.len(), fetched), (100, 2));
+
+        let trades =
+            query::page_trade_history(100, |page| history_page(page, 100, true), |_| true).unwrap();
+        assert_eq!(trades.len(), 100);
+
+        // Endless history is cut off
+        let trades = query::page_trade_history(
+            1,
+            |page| Ok(vec![trade(page, "LTC_BTC", "Buy", 1.0, 0.01, 0.0)]),
+            |_| false,
+        )
+        .unwrap();
+        assert_eq!(trades.len(), 1000);
+    }
+
+    ///////////////
+    // PnL tests //
+    ///////////////
+
+    /// Realized PnL of selling 1.5 then 1 LTC after buying 1 at 10 and 1 at 20, fees in quote
+    fn realized(method: pnl::CostMethod) -> (pnl::PnlEngine, Vec<pnl::RealizedPnl>) {
+        let mut engine = pnl::PnlEngine::new(method);
+        // Applied in time order whatever the order given
+        let trades = vec![
+            trade(4, "LTC_USDT", "Sell", 1.0, 40.0, 0.4),
+            trade(2, "LTC_USDT", "Buy", 1.0, 20.0, 0.2),
+            trade(3, "LTC_USDT", "Sell", 1.5, 30.0, 0.45),
+            trade(1, "LTC_USDT", "Buy", 1.0, 10.0, 0.1),
+        ];
+        let realized = engine.ingest(&trades);
+        assert!(engine.ingest(&trades).is_empty());
+        assert_eq!(realized.len(), 2);
+        assert_eq!(realized[0].trade_id, 3);
+        assert_close(realized[0].proceeds, 44.55);
+        assert_close(realized[0].unmatched, 0.0);
+        // Only the 0.5 LTC still held has a cost basis
+        assert_eq!(realized[1].trade_id, 4);
+        assert_close(realized[1].unmatched, 0.5);
+        assert_close(realized[1].proceeds, 19.8);
+        assert!(engine.positions().is_empty());
+        (engine, realized)
+    }
+
+    #[test]
+    fn test_pnl_fifo() {
+        let (engine, realized) = realized(pnl::CostMethod::Fifo);
+        assert_close(realized[0].cost, 20.2);
+        assert_close(realized[0].pnl, 24.35);
+        assert_close(realized[1].cost, 10.1);
+        assert_close(realized[1].pnl, 9.7);
+        assert_close(engine.realized_totals()["USDT"], 34.05);
+    }
+
+    #[test]
+    fn test_pnl_lifo() {
+        let (engine, realized) = realized(pnl::CostMethod::Lifo);
+        assert_close(realized[0].cost, 25.25);
+        assert_close(realized[0].pnl, 19.3);
+        assert_close(realized[1].cost, 5.05);
+        assert_close(realized[1].pnl, 14.75);
+        assert_close(engine.realized_totals()["USDT"], 34.05);
+    }
+
+    #[test]
+    fn test_pnl_average() {
+        let (engine, realized) = realized(pnl::CostMethod::Average);
+        assert_close(realized[0].cost, 22.725);
+        assert_close(realized[0].pnl, 21.825);
+        assert_close(realized[1].cost, 7.575);
+        assert_close(realized[1].pnl, 12.225);
+        assert_close(engine.realized_totals()["USDT"], 34.05);
+    }
+
+    #[test]
+    fn test_pnl_open_position() {
+        let mut engine = pnl::PnlEngine::new(pnl::CostMethod::Fifo);
+        engine.ingest(&[
+            trade(1, "LTC_USDT", "Buy", 1.0, 10.0, 0.1),
+            trade(2, "LTC_USDT", "Buy", 1.0, 20.0, 0.2),
+            trade(3, "LTC_USDT", "Sell", 0.5, 30.0, 0.15),
+        ]);
+        let position = engine.position("LTC").unwrap();
+        assert_close(position.amount(), 1.5);
+        assert_close(position.costs()["USDT"], 25.25);
+    }
+
+    ///////////////////////
+    // Kill switch tests //
+    ///////////////////////
+
+    fn kill_switch() -> killswitch::KillSwitch {
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        killswitch::KillSwitch::new(Arc::new(Client::with_credentials(credentials())))
+            .attempts(3)
+            .retry_delay(Duration::from_millis(0))
+            .poll_interval(Duration::from_millis(10))
+    }
+
+    #[test]
+    fn test_kill_switch_retries_until_confirmed() {
+        use error::{Error, ErrorType};
+
+        let switch = kill_switch();
+        let mut cancels = 0;
+        let mut open = vec![2, 0].into_iter();
+        let result = switch.trigger_with(
+            || {
+                cancels += 1;
+                Ok(())
+            },
+            || Ok(open.next().unwrap()),
+        );
+        assert!(result.is_ok());
+        assert_eq!(cancels, 2);
+
+        // Gives up after the attempts with the last problem
+        let error = switch.trigger_with(|| Ok(()), || Ok(1)).unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::KillSwitchError));
+        assert_eq!(
+            error.message,
+            "Cancelling not confirmed after 3 attempts: 1 orders still open"
+        );
+        let error = switch
+            .trigger_with(
+                || {
+                    Err(Error {
+                        error_type: ErrorType::APIError,
+                        message: "Maintenance".to_string(),
+                    })
+                },
+                || panic!("cancel failed"),
+            )
+            .unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::KillSwitchError));
+        assert!(error
+            .message
+            .ends_with("Error while calling TradeSatoshi API: Maintenance"));
+    }
+
+    #[test]
+    fn test_kill_switch_watchers_stop() {
+        let switch = kill_switch();
+        let path = std::env::temp_dir().join("tradesatoshi-kill-switch-never-created");
+        let watcher = switch.watch_file(path);
+        assert!(!watcher.stop().unwrap());
+
+        let watcher = switch.watch_signal(signal_hook::SIGUSR2).unwrap();
+        assert!(!watcher.stop().unwrap());
+        drop(switch.watch_signal(signal_hook::SIGUSR2).unwrap());
+        assert!(!switch.is_triggered());
+    }
+
+    //////////////////////////
+    // Order rounding tests //
+    //////////////////////////
+
+    #[test]
+    fn test_floor_to() {
+        assert_eq!(markets::floor_to(0.1 + 0.2, 1), 0.3);
+        assert_eq!(markets::floor_to(0.1 + 0.2, 8), 0.3);
+        assert_eq!(markets::floor_to(1.15, 2), 1.15);
+        assert_eq!(markets::floor_to(2.999, 2), 2.99);
+        assert_eq!(markets::floor_to(0.99999, 4), 0.9999);
+        assert_eq!(markets::floor_to(1.0, 0), 1.0);
+        assert_eq!(markets::floor_to(0.000_000_03, 8), 0.000_000_03);
+        assert_eq!(markets::floor_to(0.000_000_039, 8), 0.000_000_03);
+        assert_eq!(markets::floor_to(0.000_000_009, 8), 0.0);
+        assert_eq!(markets::floor_to(12_345.678, 8), 12_345.678);
+    }
+
+    #[test]
+    fn test_round_to() {
+        assert_eq!(markets::round_to(0.1 + 0.2, 1), 0.3);
+        assert_eq!(markets::round_to(1.005, 2), 1.01);
+        assert_eq!(markets::round_to(1.004, 2), 1.0);
+        assert_eq!(markets::round_to(0.125, 2), 0.13);
+        assert_eq!(markets::round_to(0.000_000_015, 8), 0.000_000_02);
+        assert_eq!(markets::round_to(0.000_000_014, 8), 0.000_000_01);
+        assert_eq!(markets::round_to(0.000_000_004, 8), 0.0);
+    }
+
+    ///////////////////////////
+    // Nonce rejection tests //
+    ///////////////////////////
+
+    #[test]
+    fn test_is_nonce_rejection() {
+        assert!(clock::is_nonce_rejection("Invalid nonce"));
+        assert!(clock::is_nonce_rejection("Nonce has already been used"));
+        assert!(clock::is_nonce_rejection("Invalid timestamp: 1552521200"));
+        assert!(clock::is_nonce_rejection("Request timestamp expired"));
+        assert!(!clock::is_nonce_rejection(
+            "No trades found after timestamp 1552521200"
+        ));
+        assert!(!clock::is_nonce_rejection("Market LTC_BTC is closed"));
+        assert!(!clock::is_nonce_rejection("Insufficient funds"));
+    }
+
+    #[test]
+    fn test_clock_correct() {
+        use chrono::{Duration, Utc};
+        use clock::{Clock, NonceScheme};
+
+        let now = Utc::now();
+        let server = now + Duration::seconds(5);
+        for &scheme in &[NonceScheme::Random, NonceScheme::Timestamp] {
+            let clock = Clock::new(scheme);
+            assert!(clock.correct().is_none());
+            clock.observe_server_time(server, now, now);
+            let corrected = clock.correct();
+            match scheme {
+                NonceScheme::Random => {
+                    assert!(corrected.is_none());
+                    assert_eq!(clock.offset(), Duration::zero());
+                }
+                NonceScheme::Timestamp => {
+                    assert_eq!(corrected, Some(Duration::milliseconds(5500)));
+                    assert_eq!(clock.offset(), Duration::milliseconds(5500));
+                }
+            }
+        }
+    }
+
+    /////////////////
+    // Error tests //
+    /////////////////
+
+    #[test]
+    fn test_error_display() {
+        use error::{Error, ErrorType};
+        use std::error::Error as StdError;
+
+        for error_type in vec![
+            ErrorType::APIError,
+            ErrorType::JsonError,
+            ErrorType::NoResults,
+            ErrorType::IoError,
+            ErrorType::ValidationError,
+            ErrorType::RiskError,
+            ErrorType::Frozen,
+            ErrorType::KillSwitchError,
+            ErrorType::WithdrawRejected,
+            ErrorType::InvalidAddress,
+            ErrorType::Timeout,
+            ErrorType::StoreError,
+            ErrorType::MetricsError,
+            ErrorType::InvalidCredentials,
+            ErrorType::NonceRejected,
+            ErrorType::Panicked,
+        ] {
+            let error = Error {
+                error_type,
+                message: "insufficient funds".to_string(),
+            };
+            let display = error.to_string();
+            #[allow(deprecated)]
+            let description = error.description();
+            assert!(display.starts_with(description), "{}", display);
+            assert!(display.contains("insufficient funds"), "{}", display);
+        }
+    }
+
+    ///////////////////
+    // Gateway tests //
+    ///////////////////
+
+    #[cfg(feature = "gateway")]
+    fn gateway() -> gateway::Gateway {
+        gateway::Gateway::new(Client::with_credentials(credentials()))
+            .token("reader", "read-token", &[gateway::Scope::Read])
+            .token(
+                "trader",
+                "trade-token",
+                &[gateway::Scope::Read, gateway::Scope::Trade],
+            )
+    }
+
+    #[cfg(feature = "gateway")]
+    #[test]
+    fn test_gateway_rejects_missing_and_unknown_tokens() {
+        use tiny_http::Method;
+
+        let gateway = gateway();
+        for authorization in &[
+            None,
+            Some("Bearer wrong-token"),
+            Some("read-token"),
+            Some("Bearer "),
+        ] {
+            let caller = gateway.bearer(*authorization);
+            assert!(caller.is_none());
+            assert_eq!(gateway.reply(caller, &Method::Get, "/balances", "").0, 401);
+        }
+        assert!(gateway.bearer(Some("Bearer read-token")).is_some());
+    }
+
+    #[cfg(feature = "gateway")]
+    #[test]
+    fn test_gateway_checks_scopes() {
+        use tiny_http::Method;
+
+        let gateway = gateway();
+        let reader = gateway.bearer(Some("Bearer read-token"));
+        let trader = gateway.bearer(Some("Bearer trade-token"));
+        let order = r#"{"market":"LTC_BTC","type":"Buy","amount":1,"price":0.01}"#;
+        let withdraw =
+            r#"{"currency":"BTC","address":"1BoatSLRHtKNngkdXEeobR76b53LETtpyT","amount":1}"#;
+        assert_eq!(
+            gateway.reply(reader, &Method::Post, "/orders", order).0,
+            403
+        );
+        assert_eq!(gateway.reply(reader, &Method::Post, "/cancel", "{}").0, 403);
+        assert_eq!(
+            gateway
+                .reply(reader, &Method::Post, "/withdraw", withdraw)
+                .0,
+            403
+        );
+        assert_eq!(
+            gateway
+                .reply(trader, &Method::Post, "/withdraw", withdraw)
+                .0,
+            403
+        );
+        assert_eq!(
+            gateway.reply(trader, &Method::Post, "/transfer", "{}").0,
+            403
+        );
+    }
+
+    #[cfg(feature = "gateway")]
+    #[test]
+    fn test_gateway_unknown_routes() {
+        use tiny_http::Method;
+
+        let gateway = gateway();
+        let reader = gateway.bearer(Some("Bearer read-token"));
+        let trader = gateway.bearer(Some("Bearer trade-token"));
+        assert_eq!(gateway.reply(reader, &Method::Get, "/nope", "").0, 404);
+        assert_eq!(
+            gateway
+                .reply(reader, &Method::Get, "/balances/extra/parts", "")
+                .0,
+            404
+        );
+        assert_eq!(gateway.reply(trader, &Method::Post, "/nope", "{}").0, 404);
+        assert_eq!(gateway.reply(trader, &Method::Delete, "/orders", "").0, 404);
+    }
+
+    /////////////////////////
+    // Order tracker tests //
+    /////////////////////////
+
+    /// Tracker following buy order 10 of 1 LTC, trade 1 was made before tracking
+    fn tracker() -> orders::OrderTracker {
+        let mut tracker = orders::OrderTracker::new();
+        tracker.prime_with(&[trade(1, "LTC_BTC", "Buy", 1.0, 0.01, 0.0)], &[]);
+        let submitted = values::SubmitOrder {
+            order_id: 10,
+            filled: Vec::new(),
+        };
+        tracker.track(
+            &submitted,
+            "LTC_BTC".to_string(),
+            "Buy".to_string(),
+            1.0,
+            0.01,
+        );
+        tracker
+    }
+
+    fn order_state(remaining: f32, status: &str) -> values::Order {
+        let mut order = open_order(10, remaining);
+        order.status = status.to_string();
+        order
+    }
+
+    fn unexpected_get_order(id: u32) -> query::Result<values::Order> {
+        panic!("order {} is open", id)
+    }
+
+    #[test]
+    fn test_tracker_partial_fill() {
+        let mut tracker = tracker();
+        let mut trades = vec![
+            trade(1, "LTC_BTC", "Buy", 1.0, 0.01, 0.0),
+            trade(2, "LTC_BTC", "Buy", 0.4, 0.01, 0.0001),
+        ];
+        let events = tracker
+            .poll_with(&trades, &[open_order(10, 0.6)], unexpected_get_order)
+            .unwrap();
+        match events[..] {
+            [orders::OrderEvent::PartiallyFilled {
+                order_id: 10,
+                filled,
+                remaining,
+                price,
+                fee,
+                ..
+            }] => {
+                assert_close(filled, 0.4);
+                assert_close(remaining, 0.6);
+                assert_close(price, 0.01);
+                assert_close(fee, 0.0001);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        let events = tracker
+            .poll_with(&trades, &[open_order(10, 0.6)], unexpected_get_order)
+            .unwrap();
+        assert!(events.is_empty());
+
+        trades.push(trade(3, "LTC_BTC", "Buy", 0.6, 0.02, 0.0002));
+        let events = tracker
+            .poll_with(&trades, &[], |_| Ok(order_state(0.0, "Filled")))
+            .unwrap();
+        match events[..] {
+            [orders::OrderEvent::Filled {
+                order_id: 10,
+                filled,
+                price,
+                fee,
+                ..
+            }] => {
+                assert_close(filled, 1.0);
+                assert_close(price, 0.016);
+                assert_close(fee, 0.0003);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert!(!tracker.is_tracking());
+        assert!(!tracker.is_primed());
+    }
+
+    #[test]
+    fn test_tracker_fill_on_first_poll() {
+        let mut tracker = tracker();
+        let filled = |_| Ok(order_state(0.0, "Filled"));
+        // The fill shows up before its trade, the order waits for it
+        let old = vec![trade(1, "LTC_BTC", "Buy", 1.0, 0.01, 0.0)];
+        assert!(tracker.poll_with(&old, &[], filled).unwrap().is_empty());
+        assert!(tracker.is_tracking());
+
+        let trades = vec![
+            trade(1, "LTC_BTC", "Buy", 1.0, 0.01, 0.0),
+            trade(2, "LTC_BTC", "Buy", 1.0, 0.009, 0.0001),
+        ];
+        match tracker.poll_with(&trades, &[], filled).unwrap()[..] {
+            [orders::OrderEvent::Filled { filled, price, .. }] => {
+                assert_close(filled, 1.0);
+                assert_close(price, 0.009);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+
+        // Trades that never show up delay the event by the grace polls only
+        let mut tracker = self::tracker().grace_polls(1);
+        assert!(tracker.poll_with(&old, &[], filled).unwrap().is_empty());
+        match tracker.poll_with(&old, &[], filled).unwrap()[..] {
+            [orders::OrderEvent::Filled { price, fee, .. }] => {
+                assert_close(price, 0.01);
+                assert_close(fee, 0.0);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tracker_cancel_after_partial_fill() {
+        let mut tracker = tracker();
+        let trades = vec![trade(2, "LTC_BTC", "Buy", 0.4, 0.01, 0.0001)];
+        let events = tracker
+            .poll_with(&trades, &[open_order(10, 0.6)], unexpected_get_order)
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let events = tracker
+            .poll_with(&trades, &[], |_| Ok(order_state(0.6, "Cancelled")))
+            .unwrap();
+        match events[..] {
+            [orders::OrderEvent::Cancelled {
+                order_id: 10,
+                filled,
+                remaining,
+                price,
+                fee,
+                ..
+            }] => {
+                assert_close(filled, 0.4);
+                assert_close(remaining, 0.6);
+                assert_close(price, 0.01);
+                assert_close(fee, 0.0001);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert!(!tracker.is_tracking());
+    }
+
+    /////////////////
+    // Store tests //
+    /////////////////
+
+    /// Account history served newest first without network
+    #[cfg(feature = "sqlite")]
+    #[derive(Default)]
+    struct Exchange {
+        trades: Vec<values::TradeHistory>,
+        /// Serve the first page whatever page is asked for
+        ignore_pages: bool,
+        open: Vec<values::Order>,
+        closed: Vec<values::Order>,
+        history: Vec<values::Trade>,
+    }
+
+    #[cfg(feature = "sqlite")]
+    impl store::Source for Exchange {
+        fn trade_history(&self, count: u32, page: u32) -> query::Result<Vec<values::TradeHistory>> {
+            let page = if self.ignore_pages { 0 } else { page };
+            let start = (page * count) as usize;
+            Ok(self
+                .trades
+                .iter()
+                .rev()
+                .skip(start)
+                .take(count as usize)
+                .cloned()
+                .collect())
+        }
+
+        fn deposits(&self, _count: u32) -> query::Result<Vec<values::Transaction>> {
+            Ok(Vec::new())
+        }
+
+        fn withdrawals(&self, _count: u32) -> query::Result<Vec<values::Transaction>> {
+            Ok(Vec::new())
+        }
+
+        fn orders(&self, count: u32) -> query::Result<Vec<values::Order>> {
+            Ok(self.open.iter().take(count as usize).cloned().collect())
+        }
+
+        fn order(&self, id: u32) -> query::Result<values::Order> {
+            match self.closed.iter().find(|order| order.id == id) {
+                Some(order) => Ok(order.clone()),
+                None => panic!("order {} is not closed", id),
+            }
+        }
+
+        fn market_history(&self, market: &str, count: u32) -> query::Result<Vec<values::Trade>> {
+            assert_eq!(market, "LTC_BTC");
+            Ok(self
+                .history
+                .iter()
+                .rev()
+                .take(count as usize)
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn market_trade(id: u32) -> values::Trade {
+        values::Trade {
+            id,
+            time_stamp: format!("2019-03-{:02}T12:00:00", id),
+            quantity: 1.0,
+            price: 0.01,
+            total: 0.01,
+            order_type: "Buy".to_string(),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_store_migrations() {
+        let store = store::Store::open_in_memory().unwrap();
+        assert_eq!(store.schema_version().unwrap(), 1);
+        assert_eq!(store.last_sync().unwrap(), None);
+        assert!(store.trades(None).unwrap().is_empty());
+        assert!(store.orders().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_store_sync_trades() {
+        let mut exchange = Exchange {
+            trades: (1..6)
+                .map(|id| trade(id, "LTC_BTC", "Buy", 1.0, 0.01, 0.0))
+                .collect(),
+            ..Exchange::default()
+        };
+        let mut store = store::Store::open_in_memory().unwrap().count(2);
+        assert_eq!(store.sync_from(&exchange).unwrap().trades, 5);
+        exchange
+            .trades
+            .push(trade(6, "LTC_BTC", "Sell", 1.0, 0.01, 0.0));
+        assert_eq!(store.sync_from(&exchange).unwrap().trades, 1);
+        let ids: Vec<u32> = store
+            .trades(None)
+            .unwrap()
+            .iter()
+            .map(|trade| trade.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+        assert!(store.last_sync().unwrap().is_some());
+
+        // Paging stops on a page of stored trades
+        exchange.ignore_pages = true;
+        let mut store = store::Store::open_in_memory().unwrap().count(2);
+        assert_eq!(store.sync_from(&exchange).unwrap().trades, 2);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_store_sync_closed_orders() {
+        let mut exchange = Exchange {
+            open: vec![open_order(10, 1.0), open_order(11, 1.0)],
+            ..Exchange::default()
+        };
+        let mut store = store::Store::open_in_memory().unwrap();
+        assert_eq!(store.sync_from(&exchange).unwrap().orders, 2);
+        assert_eq!(store.sync_from(&exchange).unwrap().orders, 0);
+
+        // Order 10 was cancelled between syncs
+        let mut cancelled = open_order(10, 1.0);
+        cancelled.status = "Cancelled".to_string();
+        exchange.open = vec![open_order(11, 0.5)];
+        exchange.closed = vec![cancelled];
+        assert_eq!(store.sync_from(&exchange).unwrap().orders, 2);
+        let orders = store.orders().unwrap();
+        assert_eq!(orders[0].status, "Cancelled");
+        assert_close(orders[1].remaining, 0.5);
+
+        // Closed orders are not fetched again
+        exchange.closed.clear();
+        assert_eq!(store.sync_from(&exchange).unwrap().orders, 0);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_store_sync_market_history() {
+        let mut exchange = Exchange {
+            history: (1..6).map(market_trade).collect(),
+            ..Exchange::default()
+        };
+        let mut store = store::Store::open_in_memory()
+            .unwrap()
+            .market("LTC_BTC")
+            .count(2);
+        assert_eq!(store.sync_from(&exchange).unwrap().market_history, 5);
+        exchange.history.extend((6..9).map(market_trade));
+        assert_eq!(store.sync_from(&exchange).unwrap().market_history, 3);
+        assert_eq!(store.market_history("LTC_BTC").unwrap().len(), 8);
+    }
+
+    /////////////////////
+    // Valuation tests //
+    /////////////////////
+
+    fn summary(market: &str, last: f32) -> values::MarketSummary {
+        values::MarketSummary {
+            market: market.to_string(),
+            high: last,
+            low: last,
+            volume: 0.0,
+            last,
+            base_volume: 0.0,
+            bid: last,
+            ask: last,
+            change: 0.0,
+            open_buy_orders: 0,
+            open_sell_orders: 0,
+        }
+    }
+
+    fn balance(currency: &str, total: f32) -> values::Balance {
+        values::Balance {
+            currency: currency.to_string(),
+            currency_long: currency.to_string(),
+            available: total,
+            total,
+            held_for_trades: 0.0,
+            unconfirmed: 0.0,
+            pending_withdraw: 0.0,
+            address: None,
+        }
+    }
+
+    fn market_graph() -> graph::MarketGraph {
+        graph::MarketGraph::new(vec![
+            summary("BTC_USDT", 20_000.0),
+            summary("LTC_BTC", 0.01),
+            summary("DOGE_LTC", 0.001),
+            // No trades yet, DASH is priced through BTC
+            summary("DASH_USDT", 0.0),
+            summary("DASH_BTC", 0.005),
+            summary("XMR_BTC", 0.0),
+        ])
+    }
+
+    #[test]
+    fn test_value_balances() {
+        let balances = vec![
+            balance("USDT", 10.0),
+            balance("BTC", 0.5),
+            balance("LTC", 2.0),
+            balance("DOGE", 1000.0),
+            balance("DASH", 1.0),
+            balance("XMR", 1.0),
+            balance("ETH", 0.0),
+        ];
+        let valuation = valuation::Valuer::new("USDT").value_balances(&balances, &market_graph());
+        let routes: Vec<(&str, Vec<&str>, f32)> = valuation
+            .balances
+            .iter()
+            .map(|value| {
+                let route = value.route.iter().map(|market| market.as_str()).collect();
+                (value.currency.as_str(), route, value.value.total)
+            })
+            .collect();
+        assert_eq!(routes[0], ("USDT", vec![], 10.0));
+        assert_eq!(routes[1], ("BTC", vec!["BTC_USDT"], 10_000.0));
+        assert_eq!(routes[2].1, vec!["LTC_BTC", "BTC_USDT"]);
+        assert_close(routes[2].2, 400.0);
+        assert_eq!(routes[3].1, vec!["DOGE_LTC", "LTC_BTC", "BTC_USDT"]);
+        assert_close(routes[3].2, 200.0);
+        assert_eq!(routes[4].1, vec!["DASH_BTC", "BTC_USDT"]);
+        assert_close(routes[4].2, 100.0);
+        assert_eq!(routes.len(), 5);
+        assert_eq!(valuation.unpriced, vec!["XMR"]);
+        assert!((valuation.totals.total - 10_710.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_value_balances_routes() {
+        let graph = market_graph();
+        // Buying the reference currency divides by the price
+        let valuation =
+            valuation::Valuer::new("BTC").value_balances(&[balance("USDT", 20_000.0)], &graph);
+        assert_close(valuation.balances[0].rate, 1.0 / 20_000.0);
+        assert_close(valuation.totals.total, 1.0);
+
+        let valuation = valuation::Valuer::new("USDT")
+            .max_hops(2)
+            .value_balances(&[balance("DOGE", 1000.0)], &graph);
+        assert_eq!(valuation.unpriced, vec!["DOGE"]);
+
+        let valuation = valuation::Valuer::new("USDT")
+            .price_source(valuation::PriceSource::Bid)
+            .value_balances(&[balance("LTC", 1.0)], &graph);
+        assert_close(valuation.totals.available, 200.0);
+    }
+
+    /////////////////////
+    // Arbitrage tests //
+    /////////////////////
+
+    /// LTC is cheaper through BTC than on LTC_USDT
+    fn triangle_graph() -> graph::MarketGraph {
+        graph::MarketGraph::new(vec![
+            summary("BTC_USDT", 20_000.0),
+            summary("LTC_BTC", 0.01),
+            summary("LTC_USDT", 250.0),
+        ])
+    }
+
+    fn triangle_markets(triangle: &[graph::Edge]) -> Vec<&str> {
+        triangle.iter().map(|edge| edge.market.as_str()).collect()
+    }
+
+    #[test]
+    fn test_arbitrage_triangles() {
+        let client = Client::with_credentials(credentials());
+        let graph = triangle_graph();
+        assert!(arbitrage::Scanner::new(&client)
+            .triangles(&graph)
+            .is_empty());
+
+        let triangles = arbitrage::Scanner::new(&client)
+            .size("USDT", 100.0)
+            .triangles(&graph);
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(
+            triangle_markets(&triangles[0].0),
+            vec!["BTC_USDT", "LTC_BTC", "LTC_USDT"]
+        );
+        assert_eq!(triangles[0].1, 100.0);
+    }
+
+    #[test]
+    fn test_arbitrage_triangles_once_per_rotation() {
+        let client = Client::with_credentials(credentials());
+        let graph = triangle_graph();
+        // Sized in USDT, reported from BTC, the first currency
+        let triangles = arbitrage::Scanner::new(&client)
+            .reference_size("USDT", 100.0)
+            .triangles(&graph);
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(triangles[0].0[0].from, "BTC");
+        assert_eq!(
+            triangle_markets(&triangles[0].0),
+            vec!["LTC_BTC", "LTC_USDT", "BTC_USDT"]
+        );
+        assert_close(triangles[0].1, 0.005);
+
+        // Both directions of the triangle, each once
+        let triangles = arbitrage::Scanner::new(&client)
+            .reference_size("USDT", 100.0)
+            .threshold(-100.0)
+            .triangles(&graph);
+        assert_eq!(triangles.len(), 2);
+    }
+
+    ///////////////////
+    // Deposit tests //
+    ///////////////////
+
+    fn deposit(id: &str, confirmations: u32, status: &str) -> values::Transaction {
+        values::Transaction {
+            id: id.to_string(),
+            currency: "BTC".to_string(),
+            currency_long: "Bitcoin".to_string(),
+            amount: 0.5,
+            fee: 0.0,
+            address: ALLOWED.to_string(),
+            status: status.to_string(),
+            tx_id: None,
+            confirmations,
+            time_stamp: "2019-03-01T12:00:00".to_string(),
+            is_api: false,
+        }
+    }
+
+    fn deposit_events(
+        watcher: &mut deposits::DepositWatcher,
+        transactions: &[values::Transaction],
+        available: f32,
+    ) -> Vec<String> {
+        watcher
+            .poll_with(transactions, |currency| {
+                assert_eq!(currency, "BTC");
+                Ok(available)
+            })
+            .unwrap()
+            .into_iter()
+            .map(|event| match event {
+                deposits::DepositEvent::Appeared { id, .. } => format!("appeared {}", id),
+                deposits::DepositEvent::Confirmation {
+                    id, confirmations, ..
+                } => format!("confirmation {} {}", id, confirmations),
+                deposits::DepositEvent::Credited { id, .. } => format!("credited {}", id),
+                deposits::DepositEvent::Stuck { id, .. } => format!("stuck {}", id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deposit_credited_by_balance() {
+        let mut watcher = deposits::DepositWatcher::new();
+        // Credited before the watcher started
+        assert!(deposit_events(&mut watcher, &[deposit("old", 6, "Complete")], 1.0).is_empty());
+
+        let mut listed = vec![deposit("old", 6, "Complete"), deposit("a", 0, "Pending")];
+        assert_eq!(
+            deposit_events(&mut watcher, &listed, 1.0),
+            vec!["appeared a"]
+        );
+        listed[1] = deposit("a", 1, "Pending");
+        assert_eq!(
+            deposit_events(&mut watcher, &listed, 1.0),
+            vec!["confirmation a 1"]
+        );
+        assert_eq!(
+            deposit_events(&mut watcher, &listed, 1.5),
+            vec!["credited a"]
+        );
+        assert!(deposit_events(&mut watcher, &listed, 1.5).is_empty());
+    }
+
+    #[test]
+    fn test_deposit_confirmed_when_first_seen() {
+        let mut watcher = deposits::DepositWatcher::new();
+        assert!(deposit_events(&mut watcher, &[], 1.0).is_empty());
+        // Already in the balance, the status is not complete yet
+        let listed = vec![deposit("a", 3, "Processing")];
+        assert_eq!(
+            deposit_events(&mut watcher, &listed, 1.5),
+            vec!["appeared a", "credited a"]
+        );
+        assert!(deposit_events(&mut watcher, &listed, 1.5).is_empty());
+    }
+
+    #[test]
+    fn test_deposit_stuck_off_the_page() {
+        use std::time::Duration;
+
+        let mut watcher = deposits::DepositWatcher::new().stuck_after(Duration::from_millis(10));
+        assert!(deposit_events(&mut watcher, &[], 1.0).is_empty());
+        let listed = vec![deposit("a", 0, "Pending")];
+        assert_eq!(
+            deposit_events(&mut watcher, &listed, 1.0),
+            vec!["appeared a"]
+        );
+        assert!(deposit_events(&mut watcher, &[], 1.0).is_empty());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(deposit_events(&mut watcher, &[], 1.0), vec!["stuck a"]);
+        assert!(deposit_events(&mut watcher, &[], 1.0).is_empty());
+    }
+
+    //////////////////////
+    // Withdrawal tests //
+    //////////////////////
+
+    #[test]
+    fn test_withdrawal_states() {
+        use withdrawals::WithdrawalState;
+
+        let state = |status: &str| WithdrawalState::from_status(status, None);
+        assert_eq!(
+            WithdrawalState::from_status("Complete", Some("tx".to_string())),
+            WithdrawalState::Complete {
+                tx_id: Some("tx".to_string())
+            }
+        );
+        assert_eq!(
+            state("completed"),
+            WithdrawalState::Complete { tx_id: None }
+        );
+        assert_eq!(state("Incomplete"), WithdrawalState::Pending);
+        assert_eq!(state("Unconfirmed"), WithdrawalState::Pending);
+        assert_eq!(state("Pending"), WithdrawalState::Pending);
+        assert_eq!(state("Processing"), WithdrawalState::Processing);
+        assert_eq!(state("Approved"), WithdrawalState::Processing);
+        assert_eq!(state("Cancelled"), WithdrawalState::Cancelled);
+        assert_eq!(state("User canceled"), WithdrawalState::Cancelled);
+        // Negative statuses win over complete
+        assert_eq!(
+            state("Failed to complete"),
+            WithdrawalState::Failed("Failed to complete".to_string())
+        );
+        assert_eq!(
+            state("Rejected"),
+            WithdrawalState::Failed("Rejected".to_string())
+        );
+        assert_eq!(
+            state("Unprocessable"),
+            WithdrawalState::Unknown("Unprocessable".to_string())
+        );
+        assert!(state("Complete").is_final());
+        assert!(state("Error").is_final());
+        assert!(!state("Incomplete").is_final());
+    }
+
+    ///////////////////
+    // Routing tests //
+    ///////////////////
+
+    fn book(buy: &[(f32, f32)], sell: &[(f32, f32)]) -> values::PublicOrderBook {
+        let levels = |orders: &[(f32, f32)]| {
+            orders
+                .iter()
+                .map(|&(quantity, rate)| values::PublicOrder { quantity, rate })
+                .collect()
+        };
+        values::PublicOrderBook {
+            buy: levels(buy),
+            sell: levels(sell),
+        }
+    }
+
+    fn edge(sell: bool) -> graph::Edge {
+        let (from, to) = if sell { ("LTC", "BTC") } else { ("BTC", "LTC") };
+        graph::Edge {
+            market: "LTC_BTC".to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            sell,
+        }
+    }
+
+    #[test]
+    fn test_simulate_hop_sell_walks_bids() {
+        // Bids out of order, the best (highest) one is taken first
+        let book = book(&[(1.0, 0.009), (1.0, 0.01)], &[]);
+        let hop = routing::simulate_hop(&edge(true), &book, 1.5, 0.002).unwrap();
+        assert_eq!(hop.order_type(), "Sell");
+        assert_close(hop.average_price, (0.01 + 0.5 * 0.009) / 1.5);
+        assert_close(hop.worst_price, 0.009);
+        assert_close(hop.fee, 0.0145 * 0.002);
+        assert_close(hop.output, 0.0145 * 0.998);
+        assert!(hop.slippage > 0.0);
+    }
+
+    #[test]
+    fn test_simulate_hop_buy_pays_fee() {
+        let book = book(&[], &[(2.0, 0.02), (1.0, 0.01)]);
+        let hop = routing::simulate_hop(&edge(false), &book, 0.02 * 1.002, 0.002).unwrap();
+        assert_eq!(hop.order_type(), "Buy");
+        assert_close(hop.fee, 0.02 * 0.002);
+        // 0.01 BTC buys the level at 0.01, the rest buys at 0.02
+        assert_close(hop.output, 1.5);
+        assert_close(hop.worst_price, 0.02);
+        assert_close(hop.slippage, (0.02 / 1.5 - 0.01) / 0.01);
+    }
+
+    #[test]
+    fn test_simulate_hop_without_depth() {
+        let book = book(&[(1.0, 0.01)], &[(1.0, 0.01)]);
+        assert!(routing::simulate_hop(&edge(true), &book, 2.0, 0.002).is_none());
+        assert!(routing::simulate_hop(&edge(false), &book, 1.0, 0.002).is_none());
+        assert!(routing::simulate_hop(&edge(true), &book, 0.0, 0.002).is_none());
+        assert!(routing::simulate_hop(&edge(true), &book, -1.0, 0.002).is_none());
+        assert!(routing::simulate_hop(&edge(false), &self::book(&[], &[]), 1.0, 0.002).is_none());
+    }
+
+    ///////////////////
+    // Runtime tests //
+    ///////////////////
+
+    /// Records the events it sees, stops the runtime on the timer named "stop"
+    struct Recorder(Vec<String>);
+
+    impl runtime::Strategy for Recorder {
+        fn markets(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn on_event(
+            &mut self,
+            ctx: &mut runtime::Context,
+            event: &runtime::Event,
+        ) -> query::Result<()> {
+            let seen = match *event {
+                runtime::Event::OrderOpened(ref order) => format!("opened {}", order.id),
+                runtime::Event::OrderChanged(ref order) => format!("changed {}", order.id),
+                runtime::Event::OrderClosed(ref order) => format!("closed {}", order.id),
+                runtime::Event::Timer(ref name) => {
+                    if name == "stop" {
+                        ctx.stop();
+                    }
+                    format!("timer {}", name)
+                }
+                _ => "other".to_string(),
+            };
+            self.0.push(seen);
+            Ok(())
+        }
+    }
+
+    fn open_order(id: u32, remaining: f32) -> values::Order {
+        values::Order {
+            id,
+            market: "LTC_BTC".to_string(),
+            order_type: "Buy".to_string(),
+            amount: 1.0,
+            rate: 0.01,
+            remaining,
+            total: 0.01,
+            status: "Pending".to_string(),
+            timestamp: "2019-03-01T12:00:00".to_string(),
+            is_api: true,
+        }
+    }
+
+    fn seen(runtime: &mut runtime::Runtime, orders: &[values::Order]) -> Vec<String> {
+        let mut recorder = Recorder(Vec::new());
+        for event in runtime.diff_orders(orders) {
+            runtime.dispatch(&mut recorder, event);
+        }
+        recorder.0.sort();
+        recorder.0
+    }
+
+    #[test]
+    fn test_runtime_diff_orders() {
+        let client = Client::with_credentials(credentials());
+        let mut runtime = runtime::Runtime::new(&client).order_count(2);
+        assert_eq!(seen(&mut runtime, &[open_order(1, 1.0)]), vec!["opened 1"]);
+        assert!(seen(&mut runtime, &[open_order(1, 1.0)]).is_empty());
+        assert_eq!(seen(&mut runtime, &[open_order(1, 0.5)]), vec!["changed 1"]);
+        assert_eq!(seen(&mut runtime, &[]), vec!["closed 1"]);
+
+        // Orders missing from a full page may still be open
+        let mut runtime = runtime::Runtime::new(&client).order_count(1);
+        assert_eq!(seen(&mut runtime, &[open_order(1, 1.0)]), vec!["opened 1"]);
+        assert_eq!(seen(&mut runtime, &[open_order(2, 1.0)]), vec!["opened 2"]);
+        assert_eq!(seen(&mut runtime, &[]), vec!["closed 1", "closed 2"]);
+    }
+
+    #[test]
+    fn test_runtime_timers() {
+        use std::time::{Duration, Instant};
+
+        let client = Client::with_credentials(credentials());
+        let mut runtime = runtime::Runtime::new(&client);
+        let mut recorder = Recorder(Vec::new());
+        let next_poll = Instant::now() + Duration::from_secs(5);
+        assert_eq!(runtime.next_wakeup(next_poll), next_poll);
+
+        runtime
+            .context()
+            .schedule("fast", Duration::from_millis(10));
+        runtime
+            .context()
+            .schedule("slow", Duration::from_secs(3600));
+        // Timers shorter than the poll interval wake the runtime up early
+        assert!(runtime.next_wakeup(next_poll) <= Instant::now() + Duration::from_millis(10));
+        runtime.fire_timers(&mut recorder);
+        assert!(recorder.0.is_empty());
+
+        std::thread::sleep(Duration::from_millis(20));
+        runtime.fire_timers(&mut recorder);
+        assert_eq!(recorder.0, vec!["timer fast"]);
+        assert!(runtime.next_wakeup(next_poll) < next_poll);
+
+        runtime.context().cancel_timer("fast");
+        assert_eq!(runtime.next_wakeup(next_poll), next_poll);
+    }
+
+    #[test]
+    fn test_runtime_shutdown() {
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let client = Client::with_credentials(credentials());
+        let mut runtime = runtime::Runtime::new(&client);
+        let shutdown = runtime.shutdown_handle();
+        let mut recorder = Recorder(Vec::new());
+        runtime.context().schedule("stop", Duration::from_millis(0));
+        runtime.fire_timers(&mut recorder);
+        assert!(shutdown.load(Ordering::SeqCst));
+
+        // No more events once shutdown is requested
+        runtime
+            .context()
+            .schedule("after", Duration::from_millis(0));
+        runtime.fire_timers(&mut recorder);
+        assert_eq!(
+            seen(&mut runtime, &[open_order(1, 1.0)]),
+            Vec::<String>::new()
+        );
+        assert_eq!(recorder.0, vec!["timer stop"]);
+    }
+
+    /////////////////
+    // Cache tests //
+    /////////////////
+
+    fn cached_client() -> cache::CachedClient {
+        cache::CachedClient::new(query::Client::with_credentials(credentials()))
+    }
+
+    fn count_fetch(
+        fetches: &std::sync::atomic::AtomicUsize,
+        value: u32,
+    ) -> impl FnOnce(&query::Client) -> query::Result<u32> + '_ {
+        move |_| {
+            fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(value)
+        }
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        use cache::Endpoint;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let cache = cached_client().ttl(Endpoint::Ticker, Duration::from_millis(50));
+        let fetches = AtomicUsize::new(0);
+        let key = || "LTC_BTC/".to_string();
+
+        assert_eq!(
+            cache
+                .cached(Endpoint::Ticker, key(), count_fetch(&fetches, 1))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            cache
+                .cached(Endpoint::Ticker, key(), count_fetch(&fetches, 2))
+                .unwrap(),
+            1
+        );
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(
+            cache
+                .cached(Endpoint::Ticker, key(), count_fetch(&fetches, 3))
+                .unwrap(),
+            3
+        );
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // A zero TTL disables caching
+        let cache = cached_client().ttl(Endpoint::Ticker, Duration::from_secs(0));
+        assert_eq!(
+            cache
+                .cached(Endpoint::Ticker, key(), count_fetch(&fetches, 4))
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            cache
+                .cached(Endpoint::Ticker, key(), count_fetch(&fetches, 5))
+                .unwrap(),
+            5
+        );
+        assert_eq!(fetches.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_cache_invalidate() {
+        use cache::Endpoint;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cache = cached_client();
+        let fetches = AtomicUsize::new(0);
+        let fetch = |endpoint, params: &str, value| {
+            cache
+                .cached(endpoint, params.to_string(), count_fetch(&fetches, value))
+                .unwrap()
+        };
+
+        assert_eq!(fetch(Endpoint::Ticker, "LTC_BTC/", 1), 1);
+        assert_eq!(fetch(Endpoint::Ticker, "LTC_BTCX/", 2), 2);
+        assert_eq!(fetch(Endpoint::OrderBook, "LTC_BTC/both/20", 3), 3);
+        assert_eq!(fetch(Endpoint::Currencies, "", 4), 4);
+
+        cache.invalidate_market(Endpoint::Ticker, "LTC_BTC");
+        assert_eq!(fetch(Endpoint::Ticker, "LTC_BTC/", 5), 5);
+        assert_eq!(fetch(Endpoint::Ticker, "LTC_BTCX/", 6), 2);
+        assert_eq!(fetch(Endpoint::OrderBook, "LTC_BTC/both/20", 7), 3);
+
+        cache.invalidate(Endpoint::OrderBook);
+        assert_eq!(fetch(Endpoint::OrderBook, "LTC_BTC/both/20", 8), 8);
+        assert_eq!(fetch(Endpoint::Currencies, "", 9), 4);
+
+        cache.clear();
+        assert_eq!(fetch(Endpoint::Currencies, "", 10), 10);
+        assert_eq!(fetches.load(Ordering::SeqCst), 7);
+    }
+
+    /// Starts a slow request on another thread, then a second one for the same key
+    /// once the first is in flight, returning both results and the number of fetches
+    fn concurrent_fetch(
+        first: query::Result<u32>,
+    ) -> (query::Result<u32>, query::Result<u32>, usize) {
+        use cache::Endpoint;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{mpsc, Arc};
+        use std::thread;
+        use std::time::Duration;
+
+        let cache = Arc::new(cached_client());
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (started, in_flight) = mpsc::channel();
+        let slow = {
+            let cache = cache.clone();
+            let fetches = fetches.clone();
+            thread::spawn(move || {
+                cache.cached(Endpoint::Currencies, String::new(), move |_| {
+                    fetches.fetch_add(1, Ordering::SeqCst);
+                    started.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(100));
+                    first
+                })
+            })
+        };
+        in_flight.recv().unwrap();
+        let second = cache.cached(
+            Endpoint::Currencies,
+            String::new(),
+            count_fetch(&fetches, 2),
+        );
+        (slow.join().unwrap(), second, fetches.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn test_cache_coalescing() {
+        let (first, second, fetches) = concurrent_fetch(Ok(1));
+        assert_eq!(first.unwrap(), 1);
+        assert_eq!(second.unwrap(), 1);
+        assert_eq!(fetches, 1);
+    }
+
+    #[test]
+    fn test_cache_errors_not_shared() {
+        use error::{Error, ErrorType};
+
+        let (first, second, fetches) = concurrent_fetch(Err(Error {
+            error_type: ErrorType::APIError,
+            message: "Service unavailable".to_string(),
+        }));
+        assert!(matches!(
+            first,
+            Err(Error {
+                error_type: ErrorType::APIError,
+                ..
+            })
+        ));
+        assert_eq!(second.unwrap(), 2);
+        assert_eq!(fetches, 2);
+    }
+
+    ///////////////////
+    // Metrics tests //
+    ///////////////////
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_encode() {
+        use error::{Error, ErrorType};
+        use std::time::Duration;
+
+        let registry = prometheus::Registry::new();
+        let metrics = metrics::Metrics::new().unwrap();
+        metrics.register(&registry).unwrap();
+        metrics.observe_request("getbalances", "private", Duration::from_millis(250));
+        metrics.observe_request("getbalances", "private", Duration::from_millis(750));
+        metrics.observe_error(&Error {
+            error_type: ErrorType::APIError,
+            message: "Insufficient funds".to_string(),
+        });
+
+        let text = metrics::encode(&registry).unwrap();
+        assert!(text
+            .contains("tradesatoshi_requests_total{api=\"private\",endpoint=\"getbalances\"} 2"));
+        assert!(text.contains(
+            "tradesatoshi_request_duration_seconds_sum{api=\"private\",endpoint=\"getbalances\"} 1"
+        ));
+        assert!(text.contains("tradesatoshi_errors_total{class=\"APIError\"} 1"));
+        assert!(text.contains("# TYPE tradesatoshi_request_duration_seconds histogram"));
+    }
 }
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use query::{Client, Result};
+use values::*;
+
+/// Split a market name e.g. 'LTC_BTC' into base ('LTC') and quote ('BTC') currency
+pub fn split_market(market: &str) -> Option<(&str, &str)> {
+    let mut parts = market.splitn(2, '_');
+    match (parts.next(), parts.next()) {
+        (Some(base), Some(quote)) if !base.is_empty() && !quote.is_empty() => Some((base, quote)),
+        _ => None,
+    }
+}
+
+/// Trading rules of a market, TradeSatoshi does not publish these through the API
+#[derive(Debug, Clone)]
+pub struct MarketRules {
+    pub price_decimals: u32,
+    pub amount_decimals: u32,
+    /// Minimum order amount in base currency
+    pub min_base_total: f32,
+    /// Minimum order total (amount * price) in quote currency
+    pub min_quote_total: f32,
+}
+
+impl Default for MarketRules {
+    fn default() -> Self {
+        MarketRules {
+            price_decimals: 8,
+            amount_decimals: 8,
+            min_base_total: 0.0,
+            min_quote_total: 0.0001,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MarketInfo {
+    pub market: String,
+    pub base: String,
+    pub quote: String,
+    pub rules: MarketRules,
+    pub summary: MarketSummary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    UnknownMarket(String),
+    UnknownCurrency(String),
+    InactiveCurrency { currency: String, status: String },
+    InvalidOrderType(String),
+    InvalidAmount(f32),
+    InvalidPrice(f32),
+    BelowMinimumBase { amount: f32, minimum: f32 },
+    BelowMinimumQuote { total: f32, minimum: f32 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::UnknownMarket(ref market) => write!(f, "Unknown market {}", market),
+            ValidationError::UnknownCurrency(ref currency) => {
+                write!(f, "Unknown currency {}", currency)
+            }
+            ValidationError::InactiveCurrency {
+                ref currency,
+                ref status,
+            } => write!(f, "Currency {} is not active ({})", currency, status),
+            ValidationError::InvalidOrderType(ref typeo) => {
+                write!(f, "Invalid order type {}, expected 'Buy' or 'Sell'", typeo)
+            }
+            ValidationError::InvalidAmount(amount) => write!(f, "Invalid amount {}", amount),
+            ValidationError::InvalidPrice(price) => write!(f, "Invalid price {}", price),
+            ValidationError::BelowMinimumBase { amount, minimum } => {
+                write!(f, "Amount {} is below minimum {}", amount, minimum)
+            }
+            ValidationError::BelowMinimumQuote { total, minimum } => {
+                write!(f, "Total {} is below minimum {}", total, minimum)
+            }
+        }
+    }
+}
+
+/// Order rounded to the precision of its market
+#[derive(Debug, Clone)]
+pub struct ValidatedOrder {
+    pub market: String,
+    pub typeo: String,
+    pub amount: f32,
+    pub price: f32,
+}
+
+/// Round down to the given number of decimals
+pub(crate) fn floor_to(value: f32, decimals: u32) -> f32 {
+    (scaled(value, decimals).floor() / 10f64.powi(decimals as i32)) as f32
+}
+
+/// Round to the nearest value with the given number of decimals
+pub(crate) fn round_to(value: f32, decimals: u32) -> f32 {
+    (scaled(value, decimals).round() / 10f64.powi(decimals as i32)) as f32
+}
+
+/// Value in steps of the given number of decimals
+///
+/// An f32 like 1.15 is stored as 1.1499999..., so results within f32 precision
+/// of a whole or half step are taken as that step before rounding.
+fn scaled(value: f32, decimals: u32) -> f64 {
+    let scaled = f64::from(value) * 10f64.powi(decimals as i32);
+    let step = (scaled * 2.0).round() / 2.0;
+    if (scaled - step).abs() <= scaled.abs() * f64::from(f32::EPSILON) {
+        step
+    } else {
+        scaled
+    }
+}
+
+/// Cache of currencies and markets used to validate orders before submitting them
+pub struct MarketCache {
+    currencies: HashMap<String, Currency>,
+    markets: HashMap<String, MarketInfo>,
+    default_rules: MarketRules,
+    rules: HashMap<String, MarketRules>,
+    refreshed: Option<Instant>,
+}
+
+impl Default for MarketCache {
+    fn default() -> Self {
+        MarketCache::new()
+    }
+}
+
+impl MarketCache {
+    pub fn new() -> Self {
+        MarketCache {
+            currencies: HashMap::new(),
+            markets: HashMap::new(),
+            default_rules: MarketRules::default(),
+            rules: HashMap::new(),
+            refreshed: None,
+        }
+    }
+
+    /// Rules used for markets without their own rules
+    pub fn default_rules(mut self, rules: MarketRules) -> Self {
+        self.default_rules = rules;
+        self
+    }
+
+    /// Rules for a single market e.g. 'LTC_BTC'
+    pub fn market_rules(mut self, market: &str, rules: MarketRules) -> Self {
+        self.rules.insert(market.to_string(), rules);
+        self
+    }
+
+    /// Reload currencies and market summaries
+    pub fn refresh(&mut self, client: &Client) -> Result<()> {
+        let currencies = client.get_currencies()?;
+        let summaries = client.get_market_summaries()?;
+
+        self.currencies = currencies
+            .into_iter()
+            .map(|currency| (currency.currency.clone(), currency))
+            .collect();
+        self.markets.clear();
+        for summary in summaries {
+            let (base, quote) = match split_market(&summary.market) {
+                Some((base, quote)) => (base.to_string(), quote.to_string()),
+                None => continue,
+            };
+            let rules = self
+                .rules
+                .get(&summary.market)
+                .unwrap_or(&self.default_rules)
+                .clone();
+            self.markets.insert(
+                summary.market.clone(),
+                MarketInfo {
+                    market: summary.market.clone(),
+                    base,
+                    quote,
+                    rules,
+                    summary,
+                },
+            );
+        }
+        self.refreshed = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Refresh when the cache is empty or older than max_age
+    pub fn refresh_if_stale(&mut self, client: &Client, max_age: Duration) -> Result<()> {
+        match self.refreshed {
+            Some(refreshed) if refreshed.elapsed() < max_age => Ok(()),
+            _ => self.refresh(client),
+        }
+    }
+
+    pub fn market(&self, market: &str) -> Option<&MarketInfo> {
+        self.markets.get(market)
+    }
+
+    pub fn markets(&self) -> Vec<&MarketInfo> {
+        self.markets.values().collect()
+    }
+
+    pub fn currency(&self, currency: &str) -> Option<&Currency> {
+        self.currencies.get(currency)
+    }
+
+    /// Check that the currency is known and its status is 'OK'
+    pub fn check_currency(&self, currency: &str) -> ::std::result::Result<(), ValidationError> {
+        let info = self
+            .currencies
+            .get(currency)
+            .ok_or_else(|| ValidationError::UnknownCurrency(currency.to_string()))?;
+        if info.status.to_lowercase() != "ok" {
+            return Err(ValidationError::InactiveCurrency {
+                currency: currency.to_string(),
+                status: info.status.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Validate an order and round it to the market precision
+    ///
+    /// Price is rounded to the nearest step, amount is rounded down.
+    pub fn validate_order(
+        &self,
+        market: &str,
+        typeo: &str,
+        amount: f32,
+        price: f32,
+    ) -> ::std::result::Result<ValidatedOrder, ValidationError> {
+        let info = self
+            .markets
+            .get(market)
+            .ok_or_else(|| ValidationError::UnknownMarket(market.to_string()))?;
+        let typeo = match typeo.to_lowercase().as_str() {
+            "buy" => "Buy",
+            "sell" => "Sell",
+            _ => return Err(ValidationError::InvalidOrderType(typeo.to_string())),
+        };
+        self.check_currency(&info.base)?;
+        self.check_currency(&info.quote)?;
+
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(ValidationError::InvalidAmount(amount));
+        }
+        if !price.is_finite() || price <= 0.0 {
+            return Err(ValidationError::InvalidPrice(price));
+        }
+
+        let rules = &info.rules;
+        let amount = floor_to(amount, rules.amount_decimals);
+        let price = round_to(price, rules.price_decimals);
+        if amount <= 0.0 {
+            return Err(ValidationError::InvalidAmount(amount));
+        }
+        if price <= 0.0 {
+            return Err(ValidationError::InvalidPrice(price));
+        }
+        if amount < rules.min_base_total {
+            return Err(ValidationError::BelowMinimumBase {
+                amount,
+                minimum: rules.min_base_total,
+            });
+        }
+        let total = amount * price;
+        if total < rules.min_quote_total {
+            return Err(ValidationError::BelowMinimumQuote {
+                total,
+                minimum: rules.min_quote_total,
+            });
+        }
+
+        Ok(ValidatedOrder {
+            market: market.to_string(),
+            typeo: typeo.to_string(),
+            amount,
+            price,
+        })
+    }
+
+    /// Validate and submit an order, see `Client::submit_order`
+    pub fn submit_order(
+        &self,
+        client: &Client,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) -> Result<SubmitOrder> {
+        let order = self.validate_order(&market, &typeo, amount, price)?;
+        client.submit_order(order.market, order.typeo, order.amount, order.price)
+    }
+}
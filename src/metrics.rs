@@ -0,0 +1,227 @@
+use chrono;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use tiny_http::{Header, Response, Server};
+use tracing;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use error::{Error, ErrorType};
+use query::{Client, Result};
+use values::*;
+
+/// Client metrics, attach with `Client::metrics` and add to a registry with `register`
+#[derive(Clone)]
+pub struct Metrics {
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    errors: IntCounterVec,
+    rate_limit_waits: Histogram,
+    nonce_rejections: IntCounter,
+    clock_skew: Gauge,
+    balances: GaugeVec,
+    open_orders: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        Ok(Metrics {
+            requests: IntCounterVec::new(
+                Opts::new("tradesatoshi_requests_total", "API requests sent"),
+                &["endpoint", "api"],
+            )?,
+            latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "tradesatoshi_request_duration_seconds",
+                    "API request latency",
+                ),
+                &["endpoint", "api"],
+            )?,
+            errors: IntCounterVec::new(
+                Opts::new("tradesatoshi_errors_total", "Failed calls by error class"),
+                &["class"],
+            )?,
+            rate_limit_waits: Histogram::with_opts(HistogramOpts::new(
+                "tradesatoshi_rate_limit_wait_seconds",
+                "Time spent waiting on the rate limiter",
+            ))?,
+            nonce_rejections: IntCounter::new(
+                "tradesatoshi_nonce_rejections_total",
+                "Private calls rejected for their nonce",
+            )?,
+            clock_skew: Gauge::new(
+                "tradesatoshi_clock_skew_seconds",
+                "Server time minus local time from the last response",
+            )?,
+            balances: GaugeVec::new(
+                Opts::new("tradesatoshi_balance", "Balance per currency"),
+                &["currency", "state"],
+            )?,
+            open_orders: IntGaugeVec::new(
+                Opts::new("tradesatoshi_open_orders", "Open orders per market"),
+                &["market"],
+            )?,
+        })
+    }
+
+    pub fn register(&self, registry: &Registry) -> Result<()> {
+        registry.register(Box::new(self.requests.clone()))?;
+        registry.register(Box::new(self.latency.clone()))?;
+        registry.register(Box::new(self.errors.clone()))?;
+        registry.register(Box::new(self.rate_limit_waits.clone()))?;
+        registry.register(Box::new(self.nonce_rejections.clone()))?;
+        registry.register(Box::new(self.clock_skew.clone()))?;
+        registry.register(Box::new(self.balances.clone()))?;
+        registry.register(Box::new(self.open_orders.clone()))?;
+        Ok(())
+    }
+
+    /// api: 'public' or 'private'
+    pub fn observe_request(&self, endpoint: &str, api: &str, elapsed: Duration) {
+        self.requests.with_label_values(&[endpoint, api]).inc();
+        self.latency
+            .with_label_values(&[endpoint, api])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_error(&self, error: &Error) {
+        let class = format!("{:?}", error.error_type);
+        self.errors.with_label_values(&[&class]).inc();
+    }
+
+    pub fn observe_rate_limit_wait(&self, waited: Duration) {
+        self.rate_limit_waits.observe(waited.as_secs_f64());
+    }
+
+    pub fn observe_nonce_rejection(&self) {
+        self.nonce_rejections.inc();
+    }
+
+    pub fn set_clock_skew(&self, skew: chrono::Duration) {
+        self.clock_skew.set(skew.num_milliseconds() as f64 / 1000.0);
+    }
+
+    pub fn set_balances(&self, balances: &[Balance]) {
+        self.balances.reset();
+        for balance in balances {
+            let states = [
+                ("available", balance.available),
+                ("held_for_trades", balance.held_for_trades),
+                ("unconfirmed", balance.unconfirmed),
+                ("pending_withdraw", balance.pending_withdraw),
+                ("total", balance.total),
+            ];
+            for &(state, amount) in &states {
+                self.balances
+                    .with_label_values(&[&balance.currency, state])
+                    .set(f64::from(amount));
+            }
+        }
+    }
+
+    pub fn set_open_orders(&self, orders: &[Order]) {
+        let mut counts: HashMap<&str, i64> = HashMap::new();
+        for order in orders {
+            *counts.entry(&order.market).or_insert(0) += 1;
+        }
+        self.open_orders.reset();
+        for (market, count) in counts {
+            self.open_orders.with_label_values(&[market]).set(count);
+        }
+    }
+
+    /// Refresh the balance and open order gauges every interval in a background thread
+    pub fn track(&self, client: Arc<Client>, interval: Duration) -> Tracker {
+        let metrics = self.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::SeqCst) {
+                match client.get_balances() {
+                    Ok(balances) => metrics.set_balances(&balances),
+                    Err(error) => {
+                        tracing::warn!("Metrics tracker could not fetch balances: {}", error)
+                    }
+                }
+                match client.get_orders(None, Some(100)) {
+                    Ok(orders) => metrics.set_open_orders(&orders),
+                    Err(error) => {
+                        tracing::warn!("Metrics tracker could not fetch orders: {}", error)
+                    }
+                }
+                // Parked so `Tracker::stop` does not wait out the interval
+                let next = Instant::now() + interval;
+                while !stopped.load(Ordering::SeqCst) {
+                    match next.checked_duration_since(Instant::now()) {
+                        Some(left) if left > Duration::from_millis(0) => thread::park_timeout(left),
+                        _ => break,
+                    }
+                }
+            }
+        });
+        Tracker { stop, thread }
+    }
+}
+
+/// Background refresh started by `Metrics::track`
+pub struct Tracker {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Tracker {
+    /// Flag that stops the tracker when set, checked between refreshes
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Stop the tracker and wait for a refresh in progress to finish
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// Registry contents in the Prometheus text format
+pub fn encode(registry: &Registry) -> Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&registry.gather(), &mut buffer)?;
+    String::from_utf8(buffer).map_err(|error| Error {
+        error_type: ErrorType::MetricsError,
+        message: error.to_string(),
+    })
+}
+
+/// Serve the registry on `http://<addr>/metrics` from a background thread
+pub fn serve(registry: Registry, addr: &str) -> Result<JoinHandle<()>> {
+    let server = Server::http(addr).map_err(|error| Error {
+        error_type: ErrorType::IoError,
+        message: format!("Could not listen on {}: {}", addr, error),
+    })?;
+    Ok(thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let result = if request.url() == "/metrics" {
+                match encode(&registry) {
+                    Ok(body) => request.respond(Response::from_string(body).with_header(
+                        Header::from_bytes(&b"Content-Type"[..], TextEncoder::new().format_type())
+                            .expect("static header is valid"),
+                    )),
+                    Err(error) => request
+                        .respond(Response::from_string(error.to_string()).with_status_code(500)),
+                }
+            } else {
+                request.respond(Response::empty(404))
+            };
+            if let Err(error) = result {
+                tracing::warn!("Metrics endpoint could not respond: {}", error);
+            }
+        }
+    }))
+}
@@ -0,0 +1,397 @@
+use std::collections::{HashMap, HashSet};
+
+use query::{Client, Result};
+use values::*;
+
+const EPSILON: f32 = 1e-8;
+
+/// Order lifecycle events, amounts are cumulative for the whole order
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    PartiallyFilled {
+        order_id: u32,
+        market: String,
+        filled: f32,
+        remaining: f32,
+        price: f32,
+        fee: f32,
+    },
+    Filled {
+        order_id: u32,
+        market: String,
+        filled: f32,
+        price: f32,
+        fee: f32,
+    },
+    /// Price and fee cover the part filled before cancelling
+    Cancelled {
+        order_id: u32,
+        market: String,
+        filled: f32,
+        remaining: f32,
+        price: f32,
+        fee: f32,
+    },
+}
+
+struct TrackedOrder {
+    order_id: u32,
+    market: String,
+    order_type: String,
+    amount: f32,
+    rate: f32,
+    /// Filled amount already reported through an event
+    reported: f32,
+    /// Trades matched at submission time
+    trades: Vec<u32>,
+    traded: f32,
+    cost: f32,
+    fee: f32,
+    /// Polls the order waited for its trades after it was done
+    waited: u32,
+}
+
+impl TrackedOrder {
+    fn add_trade(&mut self, trade: &TradeHistory) {
+        self.traded += trade.amount;
+        self.cost += trade.amount * trade.rate;
+        self.fee += trade.fee;
+    }
+
+    /// Whether the matched trades cover the filled amount
+    fn covers(&self, filled: f32) -> bool {
+        self.traded >= filled - EPSILON
+    }
+
+    /// Average fill price, falls back to the order rate when no trades were matched
+    fn price(&self) -> f32 {
+        if self.traded > EPSILON {
+            self.cost / self.traded
+        } else {
+            self.rate
+        }
+    }
+}
+
+/// Follows submitted orders until they are filled or cancelled
+///
+/// Trade history does not reference order ids, so trades other than the ones
+/// returned by `submit_order` are matched to tracked orders by market and type.
+pub struct OrderTracker {
+    orders: HashMap<u32, TrackedOrder>,
+    /// Orders completely filled on submission (no order id given)
+    instant: Vec<TrackedOrder>,
+    seen_trades: HashSet<u32>,
+    primed: bool,
+    history_count: u32,
+    order_count: u32,
+    grace_polls: u32,
+}
+
+impl Default for OrderTracker {
+    fn default() -> Self {
+        OrderTracker::new()
+    }
+}
+
+impl OrderTracker {
+    pub fn new() -> Self {
+        OrderTracker {
+            orders: HashMap::new(),
+            instant: Vec::new(),
+            seen_trades: HashSet::new(),
+            primed: false,
+            history_count: 50,
+            order_count: 100,
+            grace_polls: 3,
+        }
+    }
+
+    /// Trade history records fetched on each poll (default: 50)
+    pub fn history_count(mut self, history_count: u32) -> Self {
+        self.history_count = history_count;
+        self
+    }
+
+    /// Open orders fetched on each poll (default: 100)
+    pub fn order_count(mut self, order_count: u32) -> Self {
+        self.order_count = order_count;
+        self
+    }
+
+    /// Polls a filled or cancelled order is kept until trade history covers its fill (default: 3)
+    ///
+    /// After that the event is reported with the trades matched so far.
+    pub fn grace_polls(mut self, grace_polls: u32) -> Self {
+        self.grace_polls = grace_polls;
+        self
+    }
+
+    pub fn is_primed(&self) -> bool {
+        self.primed
+    }
+
+    /// Whether any order is still being followed
+    pub fn is_tracking(&self) -> bool {
+        !self.orders.is_empty() || !self.instant.is_empty()
+    }
+
+    /// Submit an order and start tracking it
+    pub fn submit_order(
+        &mut self,
+        client: &Client,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) -> Result<SubmitOrder> {
+        self.prime(client)?;
+        let submitted = client.submit_order(market.clone(), typeo.clone(), amount, price)?;
+        self.watch(client, &submitted, market, typeo, amount, price)?;
+        Ok(submitted)
+    }
+
+    /// Start tracking an order submitted elsewhere
+    ///
+    /// submitted: The result of `submit_order` (required)
+    /// market, typeo, amount, price: The values the order was submitted with (required)
+    pub fn watch(
+        &mut self,
+        client: &Client,
+        submitted: &SubmitOrder,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) -> Result<()> {
+        self.prime_excluding(client, &submitted.filled)?;
+        self.track(submitted, market, typeo, amount, price);
+        Ok(())
+    }
+
+    pub(crate) fn track(
+        &mut self,
+        submitted: &SubmitOrder,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) {
+        let order = TrackedOrder {
+            order_id: submitted.order_id,
+            market,
+            order_type: typeo,
+            amount,
+            rate: price,
+            reported: 0.0,
+            trades: submitted.filled.clone(),
+            traded: 0.0,
+            cost: 0.0,
+            fee: 0.0,
+            waited: 0,
+        };
+        if submitted.order_id == 0 {
+            self.instant.push(order);
+        } else {
+            self.orders.insert(submitted.order_id, order);
+        }
+    }
+
+    /// Stop tracking an order without emitting events
+    pub fn forget(&mut self, order_id: u32) {
+        self.orders.remove(&order_id);
+        self.release();
+    }
+
+    /// Drop the seen trades once nothing is tracked, the next order primes again
+    fn release(&mut self) {
+        if !self.is_tracking() {
+            self.seen_trades.clear();
+            self.primed = false;
+        }
+    }
+
+    /// Mark trades made before tracking started as seen, so they are not matched to new orders
+    ///
+    /// Done once, on the first `submit_order` or `watch` unless called before.
+    pub fn prime(&mut self, client: &Client) -> Result<()> {
+        self.prime_excluding(client, &[])
+    }
+
+    fn prime_excluding(&mut self, client: &Client, exclude: &[u32]) -> Result<()> {
+        if self.primed {
+            return Ok(());
+        }
+        let history = client.get_trade_history(None, Some(self.history_count), None)?;
+        self.prime_with(&history, exclude);
+        Ok(())
+    }
+
+    pub(crate) fn prime_with(&mut self, history: &[TradeHistory], exclude: &[u32]) {
+        for trade in history {
+            if !exclude.contains(&trade.id) {
+                self.seen_trades.insert(trade.id);
+            }
+        }
+        self.primed = true;
+    }
+
+    /// Poll orders and trade history, returns the events since the last poll
+    ///
+    /// Nothing is updated unless every request succeeds, so the events of a
+    /// failed poll are reported by the next one.
+    pub fn poll(&mut self, client: &Client) -> Result<Vec<OrderEvent>> {
+        if !self.is_tracking() {
+            return Ok(Vec::new());
+        }
+        let trades = self.fetch_trades(client)?;
+        let open = if self.orders.is_empty() {
+            Vec::new()
+        } else {
+            client.get_orders(None, Some(self.order_count))?
+        };
+        self.poll_with(&trades, &open, |id| client.get_order(id))
+    }
+
+    /// Trade history as fetched by `poll`
+    pub fn fetch_trades(&self, client: &Client) -> Result<Vec<TradeHistory>> {
+        client.get_trade_history(None, Some(self.history_count), None)
+    }
+
+    /// Like `poll` with the trade history and open orders fetched by the caller
+    ///
+    /// trades: Latest trade history (required)
+    /// open: Open orders, may be partial (required)
+    /// get_order: Fetches a tracked order missing from `open` (required)
+    pub fn poll_with<F>(
+        &mut self,
+        trades: &[TradeHistory],
+        open: &[Order],
+        mut get_order: F,
+    ) -> Result<Vec<OrderEvent>>
+    where
+        F: FnMut(u32) -> Result<Order>,
+    {
+        let mut states = Vec::new();
+        for &id in self.orders.keys() {
+            let state = match open.iter().find(|order| order.id == id) {
+                Some(order) => order.clone(),
+                None => get_order(id)?,
+            };
+            states.push((id, state));
+        }
+        states.sort_by_key(|&(id, _)| id);
+
+        self.match_trades(trades);
+        let mut events = Vec::new();
+        let grace_polls = self.grace_polls;
+        let mut waiting = Vec::new();
+        for mut order in self.instant.drain(..) {
+            if !order.covers(order.amount) && order.waited < grace_polls {
+                order.waited += 1;
+                waiting.push(order);
+                continue;
+            }
+            events.push(OrderEvent::Filled {
+                order_id: order.order_id,
+                market: order.market.clone(),
+                filled: order.amount,
+                price: order.price(),
+                fee: order.fee,
+            });
+        }
+        self.instant = waiting;
+        for (id, state) in states {
+            if let Some(event) = self.update(id, &state) {
+                events.push(event);
+            }
+        }
+        self.release();
+        Ok(events)
+    }
+
+    fn update(&mut self, id: u32, state: &Order) -> Option<OrderEvent> {
+        let filled = state.amount - state.remaining;
+        let grace_polls = self.grace_polls;
+        let done = {
+            let order = self.orders.get_mut(&id).expect("Tracked order should exist!");
+            let closed =
+                state.status.to_lowercase().contains("cancel") || state.remaining <= EPSILON;
+            if closed && !order.covers(filled) && order.waited < grace_polls {
+                // Trades of the fill are not in the history yet
+                order.waited += 1;
+                return None;
+            }
+            if state.status.to_lowercase().contains("cancel") {
+                Some(OrderEvent::Cancelled {
+                    order_id: id,
+                    market: order.market.clone(),
+                    filled,
+                    remaining: state.remaining,
+                    price: order.price(),
+                    fee: order.fee,
+                })
+            } else if state.remaining <= EPSILON {
+                Some(OrderEvent::Filled {
+                    order_id: id,
+                    market: order.market.clone(),
+                    filled,
+                    price: order.price(),
+                    fee: order.fee,
+                })
+            } else if filled > order.reported + EPSILON {
+                order.reported = filled;
+                return Some(OrderEvent::PartiallyFilled {
+                    order_id: id,
+                    market: order.market.clone(),
+                    filled,
+                    remaining: state.remaining,
+                    price: order.price(),
+                    fee: order.fee,
+                });
+            } else {
+                None
+            }
+        };
+        if done.is_some() {
+            self.orders.remove(&id);
+        }
+        done
+    }
+
+    fn match_trades(&mut self, trades: &[TradeHistory]) {
+        let mut trades: Vec<&TradeHistory> = trades
+            .iter()
+            .filter(|trade| !self.seen_trades.contains(&trade.id))
+            .collect();
+        trades.sort_by_key(|trade| trade.id);
+
+        for trade in trades {
+            self.seen_trades.insert(trade.id);
+
+            let known = self
+                .orders
+                .values_mut()
+                .chain(self.instant.iter_mut())
+                .find(|order| order.trades.contains(&trade.id));
+            if let Some(order) = known {
+                order.add_trade(trade);
+                continue;
+            }
+
+            let mut candidates: Vec<&mut TrackedOrder> = self
+                .orders
+                .values_mut()
+                .filter(|order| {
+                    order.market == trade.market
+                        && order.order_type.to_lowercase() == trade.typeo.to_lowercase()
+                        && order.amount - order.traded > EPSILON
+                })
+                .collect();
+            candidates.sort_by_key(|order| order.order_id);
+            if let Some(order) = candidates.into_iter().next() {
+                order.add_trade(trade);
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use chrono::{DateTime, Utc};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use markets::split_market;
+use query::{page_trade_history, Client, Result};
+use values::*;
+
+const EPSILON: f32 = 1e-8;
+
+/// How sold amounts are matched against bought amounts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostMethod {
+    /// First in, first out
+    Fifo,
+    /// Last in, first out
+    Lifo,
+    /// Every sale costs the average price of the position
+    Average,
+}
+
+/// Bought amount not sold yet
+#[derive(Debug, Clone)]
+pub struct Lot {
+    pub trade_id: u32,
+    pub time: Option<DateTime<Utc>>,
+    pub amount: f32,
+    /// Cost per unit in `quote`, including the buy fee
+    pub price: f32,
+    /// Quote currency of the market the lot was bought on
+    pub quote: String,
+}
+
+/// Part of a lot taken by a sale
+struct Slice {
+    amount: f32,
+    cost: f32,
+    quote: String,
+}
+
+/// Holdings of a currency over every market it was bought on
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub currency: String,
+    pub lots: VecDeque<Lot>,
+}
+
+impl Position {
+    pub fn amount(&self) -> f32 {
+        self.lots.iter().map(|lot| lot.amount).sum()
+    }
+
+    /// Cost basis of the open amount per quote currency
+    pub fn costs(&self) -> HashMap<String, f32> {
+        let mut costs = HashMap::new();
+        for lot in &self.lots {
+            *costs.entry(lot.quote.clone()).or_insert(0.0) += lot.amount * lot.price;
+        }
+        costs
+    }
+
+    /// Quote currency of the latest lot, used to pick a market for valuation
+    pub fn latest_quote(&self) -> Option<&str> {
+        self.lots
+            .iter()
+            .max_by_key(|lot| (lot.time, lot.trade_id))
+            .map(|lot| lot.quote.as_str())
+    }
+
+    /// Take `amount` off the lots, returns the parts taken
+    fn take(&mut self, amount: f32, method: CostMethod) -> Vec<Slice> {
+        let mut slices = Vec::new();
+        if method == CostMethod::Average {
+            // Taking the same share of every lot costs the average price per quote currency
+            let open = self.amount();
+            let matched = amount.min(open);
+            if matched <= EPSILON {
+                return slices;
+            }
+            let share = matched / open;
+            for lot in &mut self.lots {
+                let quantity = lot.amount * share;
+                slices.push(Slice {
+                    amount: quantity,
+                    cost: quantity * lot.price,
+                    quote: lot.quote.clone(),
+                });
+                lot.amount -= quantity;
+            }
+            self.lots.retain(|lot| lot.amount > EPSILON);
+            return slices;
+        }
+
+        let mut remaining = amount;
+        while remaining > EPSILON {
+            let lot = match method {
+                CostMethod::Lifo => self.lots.back_mut(),
+                _ => self.lots.front_mut(),
+            };
+            let lot = match lot {
+                Some(lot) => lot,
+                None => break,
+            };
+            let quantity = remaining.min(lot.amount);
+            slices.push(Slice {
+                amount: quantity,
+                cost: quantity * lot.price,
+                quote: lot.quote.clone(),
+            });
+            lot.amount -= quantity;
+            remaining -= quantity;
+            if lot.amount <= EPSILON {
+                match method {
+                    CostMethod::Lifo => self.lots.pop_back(),
+                    _ => self.lots.pop_front(),
+                };
+            }
+        }
+        slices
+    }
+}
+
+/// Realized result of a sell trade
+#[derive(Debug, Clone)]
+pub struct RealizedPnl {
+    pub trade_id: u32,
+    pub market: String,
+    /// Currency of proceeds, cost and pnl
+    pub quote: String,
+    pub time: Option<DateTime<Utc>>,
+    pub amount: f32,
+    /// Sold amount without a cost basis in `quote`, left out of `pnl`
+    pub unmatched: f32,
+    /// Proceeds of the matched amount after the sell fee
+    pub proceeds: f32,
+    pub cost: f32,
+    pub pnl: f32,
+}
+
+/// Open position valued at the current bid of a market
+#[derive(Debug, Clone)]
+pub struct UnrealizedPnl {
+    pub market: String,
+    pub amount: f32,
+    /// Open amount without a cost basis in the market's quote currency, left out of `pnl`
+    pub unmatched: f32,
+    pub cost: f32,
+    pub value: f32,
+    pub pnl: f32,
+}
+
+/// Converts an amount of one quote currency into another at a time
+type Converter = Box<dyn Fn(&str, &str, Option<DateTime<Utc>>) -> Option<f32>>;
+
+/// Cost basis per currency and PnL in quote currency from `get_trade_history`
+pub struct PnlEngine {
+    method: CostMethod,
+    /// Keyed by currency
+    positions: HashMap<String, Position>,
+    convert: Option<Converter>,
+    realized: Vec<RealizedPnl>,
+    seen: HashSet<u32>,
+    history_count: u32,
+}
+
+impl PnlEngine {
+    pub fn new(method: CostMethod) -> Self {
+        PnlEngine {
+            method,
+            positions: HashMap::new(),
+            convert: None,
+            realized: Vec::new(),
+            seen: HashSet::new(),
+            history_count: 100,
+        }
+    }
+
+    /// Trades fetched per page in `sync` (default: 100)
+    pub fn history_count(mut self, history_count: u32) -> Self {
+        self.history_count = history_count;
+        self
+    }
+
+    /// Rate between quote currencies, so e.g. LTC bought with BTC and sold for USDT has a cost basis
+    ///
+    /// convert(from, to, time): Units of `to` per unit of `from` at `time`
+    /// (default: none, amounts bought with another quote currency count as unmatched)
+    pub fn convert<F>(mut self, convert: F) -> Self
+    where
+        F: Fn(&str, &str, Option<DateTime<Utc>>) -> Option<f32> + 'static,
+    {
+        self.convert = Some(Box::new(convert));
+        self
+    }
+
+    pub fn method(&self) -> CostMethod {
+        self.method
+    }
+
+    /// Fetch the trade history and ingest trades not seen before
+    ///
+    /// The whole history is read, as its order is not guaranteed.
+    pub fn sync(&mut self, client: &Client) -> Result<Vec<RealizedPnl>> {
+        let count = self.history_count;
+        let trades = page_trade_history(
+            count,
+            |page| client.get_trade_history(None, Some(count), Some(page)),
+            |_| false,
+        )?;
+        Ok(self.ingest(&trades))
+    }
+
+    /// Apply trades oldest first, trades already ingested are skipped
+    ///
+    /// Returns the realized PnL of the sell trades.
+    pub fn ingest(&mut self, trades: &[TradeHistory]) -> Vec<RealizedPnl> {
+        let mut trades: Vec<&TradeHistory> = trades
+            .iter()
+            .filter(|trade| !self.seen.contains(&trade.id))
+            .collect();
+        // Trades without a readable timestamp go last, in id order
+        trades.sort_by_key(|trade| {
+            let time = parse_timestamp(&trade.time_stamp);
+            (time.is_none(), time, trade.id)
+        });
+
+        let mut realized = Vec::new();
+        for trade in trades {
+            self.seen.insert(trade.id);
+            if let Some(pnl) = self.apply(trade) {
+                realized.push(pnl);
+            }
+        }
+        self.realized.extend(realized.iter().cloned());
+        realized
+    }
+
+    fn apply(&mut self, trade: &TradeHistory) -> Option<RealizedPnl> {
+        let (currency, quote) = split_market(&trade.market)?;
+        let time = parse_timestamp(&trade.time_stamp);
+        if trade.amount <= EPSILON {
+            return None;
+        }
+        let position = self
+            .positions
+            .entry(currency.to_string())
+            .or_insert_with(|| Position {
+                currency: currency.to_string(),
+                lots: VecDeque::new(),
+            });
+
+        // Fees are charged in quote currency: added to the cost of buys, taken off the proceeds of sells
+        if trade.typeo.eq_ignore_ascii_case("buy") {
+            position.lots.push_back(Lot {
+                trade_id: trade.id,
+                time,
+                amount: trade.amount,
+                price: (trade.amount * trade.rate + trade.fee) / trade.amount,
+                quote: quote.to_string(),
+            });
+            return None;
+        }
+
+        let mut matched = 0.0;
+        let mut cost = 0.0;
+        for slice in position.take(trade.amount, self.method) {
+            if let Some(rate) = rate(&self.convert, &slice.quote, quote, time) {
+                matched += slice.amount;
+                cost += slice.cost * rate;
+            }
+        }
+        let proceeds = (trade.amount * trade.rate - trade.fee) * matched / trade.amount;
+        Some(RealizedPnl {
+            trade_id: trade.id,
+            market: trade.market.clone(),
+            quote: quote.to_string(),
+            time,
+            amount: trade.amount,
+            unmatched: trade.amount - matched,
+            proceeds,
+            cost,
+            pnl: proceeds - cost,
+        })
+    }
+
+    /// Position of a currency e.g. 'LTC'
+    pub fn position(&self, currency: &str) -> Option<&Position> {
+        self.positions.get(currency)
+    }
+
+    /// Positions with an open amount
+    pub fn positions(&self) -> Vec<&Position> {
+        self.positions
+            .values()
+            .filter(|position| position.amount() > EPSILON)
+            .collect()
+    }
+
+    /// Every realized PnL since the engine was created
+    pub fn realized(&self) -> &[RealizedPnl] {
+        &self.realized
+    }
+
+    /// Realized PnL summed per quote currency
+    pub fn realized_totals(&self) -> HashMap<String, f32> {
+        let mut totals = HashMap::new();
+        for pnl in &self.realized {
+            *totals.entry(pnl.quote.clone()).or_insert(0.0) += pnl.pnl;
+        }
+        totals
+    }
+
+    /// Value the open position of a market's base currency at the ticker bid
+    pub fn unrealized(&self, market: &str, ticker: &Ticker) -> Option<UnrealizedPnl> {
+        let (currency, quote) = split_market(market)?;
+        let position = self.positions.get(currency)?;
+        let amount = position.amount();
+        if amount <= EPSILON {
+            return None;
+        }
+        let mut matched = 0.0;
+        let mut cost = 0.0;
+        for lot in &position.lots {
+            if let Some(rate) = rate(&self.convert, &lot.quote, quote, None) {
+                matched += lot.amount;
+                cost += lot.amount * lot.price * rate;
+            }
+        }
+        let value = matched * ticker.bid;
+        Some(UnrealizedPnl {
+            market: market.to_string(),
+            amount,
+            unmatched: amount - matched,
+            cost,
+            value,
+            pnl: value - cost,
+        })
+    }
+
+    /// Value every open position with `get_ticker`, in the quote currency it was last bought with
+    pub fn unrealized_all(&self, client: &Client) -> Result<Vec<UnrealizedPnl>> {
+        let mut unrealized = Vec::new();
+        for position in self.positions() {
+            let quote = match position.latest_quote() {
+                Some(quote) => quote,
+                None => continue,
+            };
+            let market = format!("{}_{}", position.currency, quote);
+            let ticker = client.get_ticker(market.clone())?;
+            if let Some(pnl) = self.unrealized(&market, &ticker) {
+                unrealized.push(pnl);
+            }
+        }
+        Ok(unrealized)
+    }
+}
+
+/// Rate from one quote currency to another, 1 for the same currency
+fn rate(
+    convert: &Option<Converter>,
+    from: &str,
+    to: &str,
+    time: Option<DateTime<Utc>>,
+) -> Option<f32> {
+    if from == to {
+        return Some(1.0);
+    }
+    convert.as_ref().and_then(|convert| convert(from, to, time))
+}
@@ -1,20 +1,35 @@
-use base64::{decode, encode};
-use hmac::{Hmac, Mac};
-use rand;
+use chrono;
+use chrono::{DateTime, Utc};
 use reqwest;
-use reqwest::header::{Authorization, ContentType, Headers, UserAgent};
-use serde_json::to_string;
-use sha2::Sha512;
+use reqwest::header::{Authorization, ContentType, Date, Headers, UserAgent};
+use serde::de::DeserializeOwned;
+use serde_json::{self, to_string, Value};
 use strum::AsStaticRef;
-use url::form_urlencoded::byte_serialize;
+use tracing;
+use tracing::field;
 
 use std;
+use std::collections::HashSet;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
+use address::{validate_address, validate_balance_address, validate_deposit_address};
+use clock::{is_nonce_rejection, Clock, NonceScheme};
+use credentials::Credentials;
 use error::{Error, ErrorType};
+#[cfg(feature = "metrics")]
+use metrics::Metrics;
+use signing::sign_request;
 use values::*;
 
 const API_URL: &str = "https://tradesatoshi.com/api/";
+/// Params masked in request logs unless `mask_params(false)` is set
+const MASKED_PARAMS: &[&str] = &["Address", "Username"];
+/// Replacement of masked values in logs
+pub(crate) const MASK: &str = "***";
+/// Pages read by `page_trade_history` at most
+const MAX_HISTORY_PAGES: u32 = 1000;
 
 /// Api type
 #[derive(AsStaticStr)]
@@ -24,87 +39,284 @@ enum Api {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
-pub type RunResult<T> = std::result::Result<T, reqwest::Error>;
 
+/// API client
+///
+/// Requests are traced with `tracing` spans. The secret, signature and
+/// `Authorization` header are never logged.
 pub struct Client {
     api_url: String,
-    api_key: String,
-    api_secret: String,
+    credentials: Credentials,
+    clock: Clock,
+    frozen: AtomicBool,
+    mask_params: bool,
+    log_bodies: bool,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl Client {
-    pub fn new(api_key: String, api_secret: String) -> Self {
+    /// api_secret: The base64 encoded secret, an error is returned when it does not decode
+    pub fn new(api_key: String, api_secret: String) -> Result<Self> {
+        Ok(Client::with_credentials(Credentials::new(api_key, api_secret)?))
+    }
+
+    pub fn with_credentials(credentials: Credentials) -> Self {
         Client {
             api_url: API_URL.to_string(),
-            api_key: api_key.to_string(),
-            api_secret: api_secret.to_string(),
+            credentials,
+            clock: Clock::new(NonceScheme::Random),
+            frozen: AtomicBool::new(false),
+            mask_params: true,
+            log_bodies: false,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Mask Address and Username params in request spans (default: true)
+    pub fn mask_params(mut self, mask_params: bool) -> Self {
+        self.mask_params = mask_params;
+        self
+    }
+
+    /// Log raw response bodies at debug level to diagnose deserialization problems (default: false)
+    ///
+    /// Bodies hold balances, addresses and order details, only enable this while debugging.
+    pub fn log_response_bodies(mut self, log_bodies: bool) -> Self {
+        self.log_bodies = log_bodies;
+        self
+    }
+
+    /// How nonces are generated (default: random)
+    ///
+    /// Timestamp nonces are corrected for the skew measured from the server's
+    /// `Date` header once a nonce is rejected.
+    pub fn nonce_scheme(mut self, scheme: NonceScheme) -> Self {
+        self.clock = Clock::new(scheme);
+        self
+    }
+
+    /// Server time minus local time as of the last response, `None` before the first response
+    pub fn clock_skew(&self) -> Option<chrono::Duration> {
+        self.clock.skew()
+    }
+
+    /// Correction currently applied to timestamp nonces
+    pub fn nonce_offset(&self) -> chrono::Duration {
+        self.clock.offset()
+    }
+
+    /// Record request counts, latencies and errors
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    fn observe_request(&self, endpoint: &str, api: &str, elapsed: Duration) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_request(endpoint, api, elapsed);
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    fn observe_request(&self, _endpoint: &str, _api: &str, _elapsed: Duration) {}
+
+    #[cfg(feature = "metrics")]
+    fn observe_error(&self, error: &Error) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_error(error);
+            if let ErrorType::NonceRejected = error.error_type {
+                metrics.observe_nonce_rejection();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    fn observe_error(&self, _error: &Error) {}
+
+    #[cfg(feature = "metrics")]
+    fn observe_clock_skew(&self, skew: chrono::Duration) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.set_clock_skew(skew);
         }
     }
 
-    fn run(&self, query: Query) -> RunResult<reqwest::Response> {
+    #[cfg(not(feature = "metrics"))]
+    fn observe_clock_skew(&self, _skew: chrono::Duration) {}
+
+    /// Refuse submit_order, submit_withdraw and submit_transfer until `rearm` is called
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+
+    /// Allow trading and withdrawals again after `freeze`
+    pub fn rearm(&self) {
+        self.frozen.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    fn check_frozen(&self) -> Result<()> {
+        if self.is_frozen() {
+            return Err(Error {
+                error_type: ErrorType::Frozen,
+                message: "Client is frozen, call rearm() to resume".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn run(&self, query: Query) -> Result<reqwest::Response> {
+        let started = Instant::now();
+        let endpoint = query.endpoint.clone();
+        let api = query.kind.as_static().to_lowercase();
+        let span = tracing::info_span!(
+            "request",
+            endpoint = %endpoint,
+            api = %api,
+            params = field::Empty,
+            nonce = field::Empty,
+            status = field::Empty,
+            latency_ms = field::Empty,
+        );
+        let _entered = span.enter();
+        if let Some(ref params) = query.params {
+            span.record("params", field::display(self.loggable_params(params)));
+        }
+
         let mut url: String = format!(
             "{}{}/{}",
             self.api_url,
-            query.kind.as_static().to_lowercase(),
+            api,
             query.endpoint
         ).to_owned();
-        match query.kind {
+        let sent = Utc::now();
+        let response = match query.kind {
             Api::Public => {
-                match query.params.map_or((), |params| url.push_str(&params.to_query_params());
+                if let Some(ref params) = query.params {
+                    url.push_str(&params.to_query_params());
+                }
                 reqwest::get(&url)
             }
             Api::Private => {
-                let headers: Headers =
-                    self.generate_header(&query.params.as_ref().unwrap(), url.clone());
-                let params = &query.params.unwrap();
+                // Serialized once so the signed body is the body sent
+                let params = query.params.unwrap_or_else(Params::new);
+                let body = to_string(&params).expect("Params always serialize");
+                let (headers, nonce) = self.generate_header(&body, &url);
+                span.record("nonce", nonce.as_str());
                 let client = reqwest::Client::new();
-                let response = client.post(&url).json(params).headers(headers).send();
-                response
+                client.post(&url).body(body).headers(headers).send()
+            }
+        };
+
+        let elapsed = started.elapsed();
+        span.record("latency_ms", elapsed.as_millis() as u64);
+        self.observe_request(&endpoint, &api, elapsed);
+        match response {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+                if let Some(&Date(date)) = response.headers().get::<Date>() {
+                    let server = DateTime::<Utc>::from(SystemTime::from(date));
+                    self.clock.observe_server_time(server, sent, Utc::now());
+                    if let Some(skew) = self.clock.skew() {
+                        self.observe_clock_skew(skew);
+                    }
+                }
+                tracing::debug!("request completed");
+                Ok(response)
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, "request failed");
+                Err(self.observed(Error::from(error)))
             }
         }
     }
 
-    fn generate_header(&self, params: &Params, url: String) -> Headers {
-        let url_encoded: String = byte_serialize(&url.as_bytes()).collect();
-        let post_params = &to_string(&params).unwrap();
-        let randn: f64 = rand::random();
-        let nonce = &randn.to_string()[2..];
-        let signature: String = format!(
-            "{}POST{}{}{}",
-            &self.api_key,
-            &url_encoded.to_lowercase(),
-            &nonce,
-            &encode(&post_params)
-        );
+    /// Params as logged, Address and Username masked unless disabled
+    fn loggable_params(&self, params: &Params) -> String {
+        let mut value = serde_json::to_value(params).unwrap_or(Value::Null);
+        if self.mask_params {
+            if let Some(object) = value.as_object_mut() {
+                for name in MASKED_PARAMS {
+                    if let Some(param) = object.get_mut(*name) {
+                        *param = Value::from(MASK);
+                    }
+                }
+            }
+        }
+        value.to_string()
+    }
+
+    /// Read and deserialize a response body, logging it in debug mode
+    fn decode_response<T: DeserializeOwned>(&self, mut resp: reqwest::Response) -> Result<T> {
+        let body = resp
+            .text()
+            .map_err(|error| self.observed(Error::from(error)))?;
+        if self.log_bodies {
+            tracing::debug!(status = resp.status().as_u16(), body = %body, "response body");
+        }
+        serde_json::from_str(&body).map_err(|error| {
+            tracing::warn!(error = %error, "could not decode response");
+            self.observed(Error::from(error))
+        })
+    }
 
-        let mut mac = Hmac::<Sha512>::new_varkey(&decode(&self.api_secret.as_bytes()).unwrap())
-            .expect("HMAC can take key of any size");
-        mac.input(&signature.as_bytes());
-        let hmac_sign = encode(&mac.result().code());
+    /// Headers signing the request body and the nonce used
+    fn generate_header(&self, body: &str, url: &str) -> (Headers, String) {
+        let nonce = self.clock.nonce();
+        let authorization = sign_request(&self.credentials, url, body, &nonce);
 
-        let header: String = format!("Basic {}:{}:{}", self.api_key, hmac_sign, &nonce);
         let mut headers = Headers::new();
         headers.set(ContentType::json());
-        headers.set(Authorization(header));
+        headers.set(Authorization(authorization.to_string()));
         headers.set(UserAgent::new(
             "Mozilla/4.0 (compatible; TradeSatoshi API Rust client)",
         ));
-        headers
+        (headers, nonce)
+    }
+
+    /// Error of an unsuccessful call, a rejected nonce corrects the clock for the next one
+    fn api_error(&self, message: String) -> Error {
+        let error_type = if is_nonce_rejection(&message) {
+            if let Some(skew) = self.clock.correct() {
+                tracing::warn!(
+                    skew_ms = skew.num_milliseconds(),
+                    "nonce rejected, correcting nonces for clock skew"
+                );
+            }
+            ErrorType::NonceRejected
+        } else {
+            ErrorType::APIError
+        };
+        self.observed(Error {
+            error_type,
+            message,
+        })
+    }
+
+    /// Count an error by class before returning it
+    fn observed(&self, error: Error) -> Error {
+        self.observe_error(&error);
+        error
     }
 
     fn check_single_response<T>(&self, api_result: APIResult<T>) -> Result<T> {
         if api_result.success {
             return Ok(api_result.result.expect("Result should exist!"));
         }
-        Err(Error {error_type: ErrorType::APIError, message: api_result.message})
+        Err(self.api_error(api_result.message.unwrap_or_default()))
     }
 
     fn check_vec_response<T>(&self, api_result: APIVecResult<T>) -> Result<Vec<T>> {
         if api_result.success {
             return Ok(api_result.result.expect("Result should exist!"));
         }
-        Err(Error {error_type: ErrorType::APIError, message: api_result.message})
-        }
+        Err(self.api_error(api_result.message.unwrap_or_default()))
     }
 
     //////////////////////////
@@ -113,9 +325,8 @@ impl Client {
 
     /// Get currencies
     pub fn get_currencies(&self) -> Result<Vec<Currency>> {
-        let mut resp = self.run(Query::new("getcurrencies".to_string(), Api::Public))
-            .unwrap();
-        let data: APIVecResult<Currency> = resp.json().unwrap();
+        let resp = self.run(Query::new("getcurrencies".to_string(), Api::Public))?;
+        let data: APIVecResult<Currency> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -123,10 +334,10 @@ impl Client {
     ///
     /// market: The market name e.g. 'LTC_BTC' (required)
     pub fn get_ticker(&self, market: String) -> Result<Ticker> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getticker".to_string(), Api::Public).params(Params::new().market(market)),
-        ).unwrap();
-        let data: APIResult<Ticker> = resp.json().unwrap();
+        )?;
+        let data: APIResult<Ticker> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -136,11 +347,11 @@ impl Client {
     /// count: The max amount of records to return (optional, default: 20)
     pub fn get_market_history(&self, market: String, count: Option<u32>) -> Result<Vec<Trade>> {
         let count: u32 = count.unwrap_or(20);
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getmarkethistory".to_string(), Api::Public)
                 .params(Params::new().market(market).count(count)),
-        ).unwrap();
-        let data: APIVecResult<Trade> = resp.json().unwrap();
+        )?;
+        let data: APIVecResult<Trade> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -148,19 +359,18 @@ impl Client {
     ///
     /// market: The market name e.g. 'LTC_BTC' (required)
     pub fn get_market_summary(&self, market: String) -> Result<MarketSummary> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getmarketsummary".to_string(), Api::Public)
                 .params(Params::new().market(market)),
-        ).unwrap();
-        let data: APIResult<MarketSummary> = resp.json().unwrap();
+        )?;
+        let data: APIResult<MarketSummary> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
     /// Get market summaries
     pub fn get_market_summaries(&self) -> Result<Vec<MarketSummary>> {
-        let mut resp = self.run(Query::new("getmarketsummaries".to_string(), Api::Public))
-            .unwrap();
-        let data: APIVecResult<MarketSummary> = resp.json().unwrap();
+        let resp = self.run(Query::new("getmarketsummaries".to_string(), Api::Public))?;
+        let data: APIVecResult<MarketSummary> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -177,11 +387,11 @@ impl Client {
     ) -> Result<PublicOrderBook> {
         let typeo: String = typeo.unwrap_or("both".to_string());
         let depth: u32 = depth.unwrap_or(20);
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getorderbook".to_string(), Api::Public)
                 .params(Params::new().market(market).typeo(typeo).depth(depth)),
-        ).unwrap();
-        let data: APIResult<PublicOrderBook> = resp.json().unwrap();
+        )?;
+        let data: APIResult<PublicOrderBook> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -192,32 +402,42 @@ impl Client {
     /// Get balance
     ///
     /// currency: The currency of the balance to return e.g. 'BTC' (required)
+    ///
+    /// A deposit address not matching the currency format is logged, the balance is still returned.
     pub fn get_balance(&self, currency: String) -> Result<Balance> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getbalance".to_string(), Api::Private)
                 .params(Params::new().currency(currency)),
-        ).unwrap();
-        let data: APIResult<Balance> = resp.json().unwrap();
-        self.check_single_response(data)
+        )?;
+        let data: APIResult<Balance> = self.decode_response(resp)?;
+        let balance = self.check_single_response(data)?;
+        check_balance_address(&balance);
+        Ok(balance)
     }
 
     /// Get balances
+    ///
+    /// Deposit addresses not matching their currency format are logged, the balances are still returned.
     pub fn get_balances(&self) -> Result<Vec<Balance>> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getbalances".to_string(), Api::Private).params(Params::new()),
-        ).unwrap();
-        let data: APIVecResult<Balance> = resp.json().unwrap();
-        self.check_vec_response(data)
+        )?;
+        let data: APIVecResult<Balance> = self.decode_response(resp)?;
+        let balances = self.check_vec_response(data)?;
+        for balance in &balances {
+            check_balance_address(balance);
+        }
+        Ok(balances)
     }
 
     /// Get order
     ///
     /// orderid: The order to return (required)
     pub fn get_order(&self, orderid: u32) -> Result<Order> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getorder".to_string(), Api::Private).params(Params::new().orderid(orderid)),
-        ).unwrap();
-        let data: APIResult<Order> = resp.json().unwrap();
+        )?;
+        let data: APIResult<Order> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -228,11 +448,11 @@ impl Client {
     pub fn get_orders(&self, market: Option<String>, count: Option<u32>) -> Result<Vec<Order>> {
         let market: String = market.unwrap_or("all".to_string());
         let count: u32 = count.unwrap_or(20);
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("getorders".to_string(), Api::Private)
                 .params(Params::new().market(market).count(count)),
-        ).unwrap();
-        let data: APIVecResult<Order> = resp.json().unwrap();
+        )?;
+        let data: APIVecResult<Order> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -249,7 +469,8 @@ impl Client {
         amount: f32,
         price: f32,
     ) -> Result<SubmitOrder> {
-        let mut resp = self.run(
+        self.check_frozen()?;
+        let resp = self.run(
             Query::new("submitorder".to_string(), Api::Private).params(
                 Params::new()
                     .market(market)
@@ -257,8 +478,8 @@ impl Client {
                     .amount(amount)
                     .price(price),
             ),
-        ).unwrap();
-        let data: APIResult<SubmitOrder> = resp.json().unwrap();
+        )?;
+        let data: APIResult<SubmitOrder> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -274,12 +495,15 @@ impl Client {
         market: Option<String>,
     ) -> Result<CancelOrder> {
         let mut params: Params = Params::new().typeo(typeo);
-        params = market.map_or(params,|market| params.market(market));
-        params = orderid.map_or(params, |orderid| params.orderid(orderid));
+        if let Some(market) = market {
+            params = params.market(market);
+        }
+        if let Some(orderid) = orderid {
+            params = params.orderid(orderid);
+        }
 
-        let mut resp = self.run(Query::new("cancelorder".to_string(), Api::Private).params(params))
-            .unwrap();
-        let data: APIResult<CancelOrder> = resp.json().unwrap();
+        let resp = self.run(Query::new("cancelorder".to_string(), Api::Private).params(params))?;
+        let data: APIResult<CancelOrder> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -297,11 +521,11 @@ impl Client {
         let market: String = market.unwrap_or("all".to_string());
         let count: u32 = count.unwrap_or(20);
         let page_num: u32 = page_num.unwrap_or(0);
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("gettradehistory".to_string(), Api::Private)
                 .params(Params::new().market(market).count(count).page_num(page_num)),
-        ).unwrap();
-        let data: APIVecResult<TradeHistory> = resp.json().unwrap();
+        )?;
+        let data: APIVecResult<TradeHistory> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -309,29 +533,33 @@ impl Client {
     ///
     /// currency: The currency to generate address for e.g. 'BTC' (required)
     pub fn generate_address(&self, currency: String) -> Result<Address> {
-        let mut resp = self.run(
+        let resp = self.run(
             Query::new("generateaddress".to_string(), Api::Private)
                 .params(Params::new().currency(currency)),
-        ).unwrap();
-        let data: APIResult<Address> = resp.json().unwrap();
-        self.check_single_response(data)
+        )?;
+        let data: APIResult<Address> = self.decode_response(resp)?;
+        let address = self.check_single_response(data)?;
+        validate_deposit_address(&address)?;
+        Ok(address)
     }
 
     /// Submit withdraw
     ///
     /// currency: The currency name e.g. 'BTC' (required)
-    /// address: The receiving address (required)
+    /// address: The receiving address, validated against the currency format (required)
     /// amount: The amount to withdraw (required)
     pub fn submit_withdraw(&self, currency: String, address: String, amount: f32) -> Result<Id> {
-        let mut resp = self.run(
-            Query::new("gettradehistory".to_string(), Api::Private).params(
+        self.check_frozen()?;
+        validate_address(&currency, &address)?;
+        let resp = self.run(
+            Query::new("submitwithdraw".to_string(), Api::Private).params(
                 Params::new()
                     .currency(currency)
                     .address(address)
                     .amount(amount),
             ),
-        ).unwrap();
-        let data: APIResult<Id> = resp.json().unwrap();
+        )?;
+        let data: APIResult<Id> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 
@@ -346,11 +574,11 @@ impl Client {
     ) -> Result<Vec<Transaction>> {
         let currency: String = currency.unwrap_or("all".to_string());
         let count: u32 = count.unwrap_or(20);
-        let mut resp = self.run(
-            Query::new("gettradehistory".to_string(), Api::Private)
+        let resp = self.run(
+            Query::new("getdeposits".to_string(), Api::Private)
                 .params(Params::new().currency(currency).count(count)),
-        ).unwrap();
-        let data: APIVecResult<Transaction> = resp.json().unwrap();
+        )?;
+        let data: APIVecResult<Transaction> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -364,12 +592,12 @@ impl Client {
         count: Option<u32>,
     ) -> Result<Vec<Transaction>> {
         let currency: String = currency.unwrap_or("all".to_string());
-        let count: u32 = match count.unwrap_or(20);
-        let mut resp = self.run(
-            Query::new("gettradehistory".to_string(), Api::Private)
+        let count: u32 = count.unwrap_or(20);
+        let resp = self.run(
+            Query::new("getwithdrawals".to_string(), Api::Private)
                 .params(Params::new().currency(currency).count(count)),
-        ).unwrap();
-        let data: APIVecResult<Transaction> = resp.json().unwrap();
+        )?;
+        let data: APIVecResult<Transaction> = self.decode_response(resp)?;
         self.check_vec_response(data)
     }
 
@@ -384,19 +612,67 @@ impl Client {
         username: String,
         amount: f32,
     ) -> Result<SubmitTransfer> {
-        let mut resp = self.run(
-            Query::new("gettradehistory".to_string(), Api::Private).params(
+        self.check_frozen()?;
+        let resp = self.run(
+            Query::new("submittransfer".to_string(), Api::Private).params(
                 Params::new()
                     .currency(currency)
                     .username(username)
                     .amount(amount),
             ),
-        ).unwrap();
-        let data: APIResult<SubmitTransfer> = resp.json().unwrap();
+        )?;
+        let data: APIResult<SubmitTransfer> = self.decode_response(resp)?;
         self.check_single_response(data)
     }
 }
 
+/// Log a deposit address of a balance that does not match the currency format
+///
+/// Balances stay readable, only `generate_address` rejects a bad address.
+fn check_balance_address(balance: &Balance) {
+    if let Err(error) = validate_balance_address(balance) {
+        tracing::warn!(
+            currency = %balance.currency,
+            error = %error.masked(),
+            "deposit address of balance is invalid"
+        );
+    }
+}
+
+/// Read trade history pages until `done` returns true for a page or the history ends
+///
+/// fetch(page): One page of at most `page_size` trades
+///
+/// Stops on a short page, on a page of trades already read (the server ignored the
+/// page number) and after `MAX_HISTORY_PAGES` pages.
+pub(crate) fn page_trade_history<F, D>(
+    page_size: u32,
+    mut fetch: F,
+    mut done: D,
+) -> Result<Vec<TradeHistory>>
+where
+    F: FnMut(u32) -> Result<Vec<TradeHistory>>,
+    D: FnMut(&[TradeHistory]) -> bool,
+{
+    let mut trades = Vec::new();
+    let mut read = HashSet::new();
+    for page in 0..MAX_HISTORY_PAGES {
+        let history = fetch(page)?;
+        let stop = history.len() < page_size as usize || done(&history);
+        let count = trades.len();
+        for trade in history {
+            if read.insert(trade.id) {
+                trades.push(trade);
+            }
+        }
+        if stop || trades.len() == count {
+            return Ok(trades);
+        }
+    }
+    tracing::warn!(pages = MAX_HISTORY_PAGES, "trade history paging stopped");
+    Ok(trades)
+}
+
 struct Query {
     kind: Api,
     endpoint: String,
@@ -0,0 +1,56 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "metrics")]
+use metrics::Metrics;
+
+/// Rate limiter spacing requests evenly over an interval
+pub struct RateLimiter {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl RateLimiter {
+    /// requests: Number of requests allowed (required)
+    /// per: The interval the requests are spread over (required)
+    pub fn new(requests: u32, per: Duration) -> Self {
+        RateLimiter {
+            interval: per / requests.max(1),
+            last: Mutex::new(None),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Report waits to `Metrics`
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Block until the next request is allowed, returns the time spent waiting
+    pub fn wait(&self) -> Duration {
+        let mut last = self.last.lock().expect("Rate limiter lock poisoned!");
+        let mut waited = Duration::from_secs(0);
+        if let Some(previous) = *last {
+            let next = previous + self.interval;
+            let now = Instant::now();
+            if next > now {
+                waited = next - now;
+                thread::sleep(waited);
+            }
+        }
+        *last = Some(Instant::now());
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.observe_rate_limit_wait(waited);
+            }
+        }
+        waited
+    }
+}
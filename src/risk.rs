@@ -0,0 +1,339 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use markets::split_market;
+use query::{page_trade_history, Client, Result};
+use values::*;
+
+/// Risk limits, every limit is optional
+///
+/// Limits can be loaded from a JSON file e.g.
+/// `{"max_order_notional": {"BTC": 0.5}, "price_collar_pct": 5.0}`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RiskLimits {
+    /// Max order total (amount * price) keyed by quote currency
+    pub max_order_notional: HashMap<String, f32>,
+    pub max_open_orders_per_market: Option<u32>,
+    /// Max total balance keyed by currency, checked when buying the currency
+    pub max_position: HashMap<String, f32>,
+    /// Max distance of the order price from the last traded price in percent
+    pub price_collar_pct: Option<f32>,
+    /// Max loss over the current UTC day keyed by quote currency
+    pub daily_loss_limit: HashMap<String, f32>,
+}
+
+impl RiskLimits {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RiskLimits> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<RiskLimits> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Check an order total (amount * price) in quote currency
+    pub fn check_notional(&self, quote: &str, notional: f32) -> Option<RiskViolation> {
+        let limit = *self.max_order_notional.get(quote)?;
+        if notional > limit {
+            return Some(RiskViolation::MaxOrderNotional { notional, limit });
+        }
+        None
+    }
+
+    /// Check the count of orders already open on the market
+    pub fn check_open_orders(&self, open: u32) -> Option<RiskViolation> {
+        let limit = self.max_open_orders_per_market?;
+        if open >= limit {
+            return Some(RiskViolation::MaxOpenOrders { open, limit });
+        }
+        None
+    }
+
+    /// Check the total balance of a currency once the order is filled
+    pub fn check_position(&self, currency: &str, position: f32) -> Option<RiskViolation> {
+        let limit = *self.max_position.get(currency)?;
+        if position > limit {
+            return Some(RiskViolation::MaxPosition {
+                currency: currency.to_string(),
+                position,
+                limit,
+            });
+        }
+        None
+    }
+
+    /// Check an order price against the last traded price
+    pub fn check_price_collar(&self, price: f32, last: f32) -> Option<RiskViolation> {
+        let limit_pct = self.price_collar_pct?;
+        if last > 0.0 && ((price - last) / last).abs() * 100.0 > limit_pct {
+            return Some(RiskViolation::PriceCollar {
+                price,
+                last,
+                limit_pct,
+            });
+        }
+        None
+    }
+
+    /// Check today's PnL in quote currency
+    pub fn check_daily_loss(&self, quote: &str, pnl: f32) -> Option<RiskViolation> {
+        let limit = *self.daily_loss_limit.get(quote)?;
+        if -pnl > limit {
+            return Some(RiskViolation::DailyLossLimit {
+                currency: quote.to_string(),
+                loss: -pnl,
+                limit,
+            });
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+    InvalidMarket(String),
+    MaxOrderNotional { notional: f32, limit: f32 },
+    MaxOpenOrders { open: u32, limit: u32 },
+    MaxPosition { currency: String, position: f32, limit: f32 },
+    PriceCollar { price: f32, last: f32, limit_pct: f32 },
+    DailyLossLimit { currency: String, loss: f32, limit: f32 },
+}
+
+impl fmt::Display for RiskViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RiskViolation::InvalidMarket(ref market) => write!(f, "Invalid market {}", market),
+            RiskViolation::MaxOrderNotional { notional, limit } => {
+                write!(f, "Order notional {} exceeds limit {}", notional, limit)
+            }
+            RiskViolation::MaxOpenOrders { open, limit } => {
+                write!(f, "{} open orders, limit is {}", open, limit)
+            }
+            RiskViolation::MaxPosition {
+                ref currency,
+                position,
+                limit,
+            } => write!(
+                f,
+                "{} position {} would exceed limit {}",
+                currency, position, limit
+            ),
+            RiskViolation::PriceCollar {
+                price,
+                last,
+                limit_pct,
+            } => write!(
+                f,
+                "Price {} is more than {}% away from last price {}",
+                price, limit_pct, last
+            ),
+            RiskViolation::DailyLossLimit {
+                ref currency,
+                loss,
+                limit,
+            } => write!(
+                f,
+                "Daily loss {} {} exceeds limit {}",
+                loss, currency, limit
+            ),
+        }
+    }
+}
+
+/// Last `daily_pnl` with the day it covers and when it was computed
+type CachedPnl = (DateTime<Utc>, Instant, HashMap<String, f32>);
+
+/// Wraps the trading methods of a client with pre-trade risk checks
+pub struct RiskManager<'a> {
+    client: &'a Client,
+    limits: RiskLimits,
+    pnl_ttl: Duration,
+    pnl: Mutex<Option<CachedPnl>>,
+}
+
+impl<'a> RiskManager<'a> {
+    pub fn new(client: &'a Client, limits: RiskLimits) -> Self {
+        RiskManager {
+            client,
+            limits,
+            pnl_ttl: Duration::from_secs(10),
+            pnl: Mutex::new(None),
+        }
+    }
+
+    /// How long the daily PnL is reused by the daily loss check (default: 10s)
+    ///
+    /// Orders submitted through the manager reset it.
+    pub fn pnl_ttl(mut self, pnl_ttl: Duration) -> Self {
+        self.pnl_ttl = pnl_ttl;
+        self
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: RiskLimits) {
+        self.limits = limits;
+    }
+
+    /// Submit order after passing all risk checks, see `Client::submit_order`
+    pub fn submit_order(
+        &self,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) -> Result<SubmitOrder> {
+        self.check_order(&market, &typeo, amount, price)?;
+        let submitted = self.client.submit_order(market, typeo, amount, price);
+        *self.pnl.lock().unwrap_or_else(|error| error.into_inner()) = None;
+        submitted
+    }
+
+    /// Cancel order, see `Client::cancel_order`
+    pub fn cancel_order(
+        &self,
+        typeo: String,
+        orderid: Option<u32>,
+        market: Option<String>,
+    ) -> Result<CancelOrder> {
+        self.client.cancel_order(typeo, orderid, market)
+    }
+
+    /// Run all configured checks for an order without submitting it
+    pub fn check_order(&self, market: &str, typeo: &str, amount: f32, price: f32) -> Result<()> {
+        let (base, quote) =
+            split_market(market).ok_or_else(|| RiskViolation::InvalidMarket(market.to_string()))?;
+        let notional = amount * price;
+        let buying = typeo.to_lowercase() == "buy";
+
+        if let Some(violation) = self.limits.check_notional(quote, notional) {
+            return Err(violation.into());
+        }
+
+        if let Some(limit) = self.limits.max_open_orders_per_market {
+            let open = self
+                .client
+                .get_orders(Some(market.to_string()), Some(limit + 1))?
+                .len() as u32;
+            if let Some(violation) = self.limits.check_open_orders(open) {
+                return Err(violation.into());
+            }
+        }
+
+        // Only buying adds to the position, selling reduces it
+        if buying && self.limits.max_position.contains_key(base) {
+            let position = self.client.get_balance(base.to_string())?.total + amount;
+            if let Some(violation) = self.limits.check_position(base, position) {
+                return Err(violation.into());
+            }
+        }
+
+        if self.limits.price_collar_pct.is_some() {
+            let last = self.client.get_ticker(market.to_string())?.last;
+            if let Some(violation) = self.limits.check_price_collar(price, last) {
+                return Err(violation.into());
+            }
+        }
+
+        if self.limits.daily_loss_limit.contains_key(quote) {
+            let pnl = self.cached_daily_pnl()?.get(quote).cloned().unwrap_or(0.0);
+            if let Some(violation) = self.limits.check_daily_loss(quote, pnl) {
+                return Err(violation.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Profit and loss of today's (UTC) trades keyed by quote currency
+    ///
+    /// Net amounts bought or sold are valued at the last ticker price. Trade
+    /// history is read until a page holds only trades from before today.
+    pub fn daily_pnl(&self) -> Result<HashMap<String, f32>> {
+        self.daily_pnl_since(start_of_day())
+    }
+
+    /// `daily_pnl` reused for `pnl_ttl` within the same day
+    fn cached_daily_pnl(&self) -> Result<HashMap<String, f32>> {
+        let today = start_of_day();
+        let mut cache = self.pnl.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some((day, computed, ref pnl)) = *cache {
+            if day == today && computed.elapsed() < self.pnl_ttl {
+                return Ok(pnl.clone());
+            }
+        }
+        let pnl = self.daily_pnl_since(today)?;
+        *cache = Some((today, Instant::now(), pnl.clone()));
+        Ok(pnl)
+    }
+
+    fn daily_pnl_since(&self, today: DateTime<Utc>) -> Result<HashMap<String, f32>> {
+        let page_size = 100;
+        let trades = page_trade_history(
+            page_size,
+            |page| {
+                self.client
+                    .get_trade_history(None, Some(page_size), Some(page))
+            },
+            |page| {
+                page.iter().all(|trade| {
+                    parse_timestamp(&trade.time_stamp).map_or(false, |time| time < today)
+                })
+            },
+        )?;
+
+        let mut pnl = HashMap::new();
+        for (market, (quote_flow, base_delta)) in trade_flows(&trades, today) {
+            let quote = match split_market(&market) {
+                Some((_, quote)) => quote.to_string(),
+                None => continue,
+            };
+            let mut value = quote_flow;
+            if base_delta != 0.0 {
+                value += base_delta * self.client.get_ticker(market.clone())?.last;
+            }
+            *pnl.entry(quote).or_insert(0.0) += value;
+        }
+        Ok(pnl)
+    }
+}
+
+/// Start of the current UTC day
+fn start_of_day() -> DateTime<Utc> {
+    let midnight = Utc::now().naive_utc().date().and_hms_opt(0, 0, 0);
+    Utc.from_utc_datetime(&midnight.expect("Midnight should exist!"))
+}
+
+/// (quote flow, base delta) per market of the trades made since a time, in any order
+pub(crate) fn trade_flows(
+    trades: &[TradeHistory],
+    since: DateTime<Utc>,
+) -> HashMap<String, (f32, f32)> {
+    let mut flows: HashMap<String, (f32, f32)> = HashMap::new();
+    for trade in trades {
+        match parse_timestamp(&trade.time_stamp) {
+            Some(time) if time >= since => {}
+            _ => continue,
+        }
+        let flow = flows.entry(trade.market.clone()).or_insert((0.0, 0.0));
+        let gross = trade.amount * trade.rate;
+        if trade.typeo.to_lowercase() == "buy" {
+            flow.0 -= gross + trade.fee;
+            flow.1 += trade.amount;
+        } else {
+            flow.0 += gross - trade.fee;
+            flow.1 -= trade.amount;
+        }
+    }
+    flows
+}
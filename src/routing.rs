@@ -0,0 +1,420 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use graph::{Edge, MarketGraph};
+use markets::{floor_to, round_to, MarketCache, MarketRules};
+use orders::{OrderEvent, OrderTracker};
+use query::{Client, Result};
+use valuation::{PriceSource, Valuer};
+use values::*;
+
+/// One conversion on a route, simulated against the order book
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub edge: Edge,
+    pub input: f32,
+    /// Received amount after fees
+    pub output: f32,
+    /// Fee paid in the quote currency of the market
+    pub fee: f32,
+    pub average_price: f32,
+    /// Price of the last order book level needed, used as limit price
+    pub worst_price: f32,
+    /// Distance of the average price from the best price as a fraction
+    pub slippage: f32,
+}
+
+impl Hop {
+    /// Order type on the market e.g. 'Sell' when converting base into quote
+    pub fn order_type(&self) -> &'static str {
+        if self.edge.sell {
+            "Sell"
+        } else {
+            "Buy"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub from: String,
+    pub to: String,
+    pub input: f32,
+    pub output: f32,
+    pub hops: Vec<Hop>,
+}
+
+/// Simulate converting `input` on a market, None when the book is too thin
+///
+/// fee: Taker fee as a fraction e.g. 0.002, charged in quote currency
+pub fn simulate_hop(edge: &Edge, book: &PublicOrderBook, input: f32, fee: f32) -> Option<Hop> {
+    if input <= 0.0 {
+        return None;
+    }
+    let mut levels: Vec<&PublicOrder> = if edge.sell {
+        book.buy.iter().collect()
+    } else {
+        book.sell.iter().collect()
+    };
+    // Best price first: highest bid when selling, lowest ask when buying
+    levels.sort_by(|a, b| {
+        let order = a.rate.partial_cmp(&b.rate).unwrap_or(Ordering::Equal);
+        if edge.sell {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    let best = levels.first()?.rate;
+
+    if edge.sell {
+        // Sell base, receive quote minus fee
+        let mut remaining = input;
+        let mut gross = 0.0;
+        let mut worst = best;
+        for level in levels {
+            if remaining <= 0.0 {
+                break;
+            }
+            let quantity = remaining.min(level.quantity);
+            gross += quantity * level.rate;
+            remaining -= quantity;
+            worst = level.rate;
+        }
+        if remaining > 0.0 {
+            return None;
+        }
+        let average_price = gross / input;
+        Some(Hop {
+            edge: edge.clone(),
+            input,
+            output: gross * (1.0 - fee),
+            fee: gross * fee,
+            average_price,
+            worst_price: worst,
+            slippage: (best - average_price) / best,
+        })
+    } else {
+        // Spend quote including fee, receive base
+        let spendable = input / (1.0 + fee);
+        let mut remaining = spendable;
+        let mut output = 0.0;
+        let mut worst = best;
+        for level in levels {
+            if remaining <= 0.0 {
+                break;
+            }
+            let cost = remaining.min(level.quantity * level.rate);
+            output += cost / level.rate;
+            remaining -= cost;
+            worst = level.rate;
+        }
+        if remaining > 0.0 || output <= 0.0 {
+            return None;
+        }
+        let average_price = spendable / output;
+        Some(Hop {
+            edge: edge.clone(),
+            input,
+            output,
+            fee: input - spendable,
+            average_price,
+            worst_price: worst,
+            slippage: (average_price - best) / best,
+        })
+    }
+}
+
+/// (filled, average price, fee) of an order
+type Fill = (f32, f32, f32);
+
+/// Executed leg of a route
+#[derive(Debug, Clone)]
+pub struct Leg {
+    pub market: String,
+    pub order_type: String,
+    pub amount: f32,
+    pub price: f32,
+    pub order_id: u32,
+    pub filled: f32,
+    /// Amount received in the target currency of the leg
+    pub received: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteExecution {
+    pub legs: Vec<Leg>,
+    /// False when a leg did not fill in time, its remainder was cancelled
+    pub completed: bool,
+}
+
+/// Finds and executes conversions between currencies
+pub struct Router<'a> {
+    client: &'a Client,
+    fee: f32,
+    max_hops: usize,
+    depth: u32,
+    candidates: usize,
+    markets: Option<MarketCache>,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Router {
+            client,
+            fee: 0.002,
+            max_hops: 3,
+            depth: 50,
+            candidates: 5,
+            markets: None,
+        }
+    }
+
+    /// Taker fee per hop as a fraction (default: 0.002)
+    pub fn fee(mut self, fee: f32) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Max markets on a route (default: 3)
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Order book depth fetched per market (default: 50)
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Routes simulated against order books, picked by last price (default: 5)
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates.max(1);
+        self
+    }
+
+    /// Markets used to validate leg orders and round them to market precision
+    /// (default: none, legs are rounded with `MarketRules::default()`)
+    pub fn markets(mut self, markets: MarketCache) -> Self {
+        self.markets = Some(markets);
+        self
+    }
+
+    /// Best route converting `amount` of `from` into `to`
+    pub fn best_route(
+        &self,
+        graph: &MarketGraph,
+        from: &str,
+        to: &str,
+        amount: f32,
+    ) -> Result<Option<Route>> {
+        Ok(self.routes(graph, from, to, amount)?.into_iter().next())
+    }
+
+    /// Routes converting `amount` of `from` into `to`, best expected output first
+    pub fn routes(
+        &self,
+        graph: &MarketGraph,
+        from: &str,
+        to: &str,
+        amount: f32,
+    ) -> Result<Vec<Route>> {
+        let valuer = Valuer::new(to).price_source(PriceSource::Last);
+        let mut paths: Vec<(f32, Vec<Edge>)> = graph
+            .paths(from, to, self.max_hops)
+            .into_iter()
+            .filter_map(|path| valuer.route_rate(graph, &path).map(|rate| (rate, path)))
+            .collect();
+        paths.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        paths.truncate(self.candidates);
+
+        let mut books: HashMap<String, PublicOrderBook> = HashMap::new();
+        let mut routes = Vec::new();
+        for (_, path) in paths {
+            if let Some(route) = self.simulate(&mut books, from, to, amount, &path)? {
+                routes.push(route);
+            }
+        }
+        routes.sort_by(|a, b| b.output.partial_cmp(&a.output).unwrap_or(Ordering::Equal));
+        Ok(routes)
+    }
+
+    fn simulate(
+        &self,
+        books: &mut HashMap<String, PublicOrderBook>,
+        from: &str,
+        to: &str,
+        amount: f32,
+        path: &[Edge],
+    ) -> Result<Option<Route>> {
+        let mut input = amount;
+        let mut hops = Vec::new();
+        for edge in path {
+            if !books.contains_key(&edge.market) {
+                let book = self
+                    .client
+                    .get_order_book(edge.market.clone(), None, Some(self.depth))?;
+                books.insert(edge.market.clone(), book);
+            }
+            let hop = match simulate_hop(edge, &books[&edge.market], input, self.fee) {
+                Some(hop) => hop,
+                None => return Ok(None),
+            };
+            input = hop.output;
+            hops.push(hop);
+        }
+        Ok(Some(Route {
+            from: from.to_string(),
+            to: to.to_string(),
+            input: amount,
+            output: input,
+            hops,
+        }))
+    }
+
+    /// Execute a route leg by leg, each leg has to fill before the next one is submitted
+    ///
+    /// fill_timeout: Time a leg may take to fill before its remainder is cancelled (required)
+    pub fn execute(&self, route: &Route, fill_timeout: Duration) -> Result<RouteExecution> {
+        let mut tracker = OrderTracker::new();
+        let mut legs = Vec::new();
+        let mut input = route.input;
+
+        for hop in &route.hops {
+            // Order amounts are in base currency
+            let amount = if hop.edge.sell {
+                input
+            } else {
+                input / (1.0 + self.fee) / hop.worst_price
+            };
+            let (amount, price) = self.round_leg(hop, amount)?;
+            let submitted = tracker.submit_order(
+                self.client,
+                hop.edge.market.clone(),
+                hop.order_type().to_string(),
+                amount,
+                price,
+            )?;
+
+            let mut leg = Leg {
+                market: hop.edge.market.clone(),
+                order_type: hop.order_type().to_string(),
+                amount,
+                price,
+                order_id: submitted.order_id,
+                filled: 0.0,
+                received: 0.0,
+            };
+            let (fill, completed) =
+                self.wait_for_fill(&mut tracker, submitted.order_id, fill_timeout)?;
+            if let Some((filled, price, fee)) = fill {
+                leg.filled = filled;
+                leg.received = if hop.edge.sell {
+                    filled * price - fee
+                } else {
+                    filled
+                };
+            }
+            input = leg.received;
+            legs.push(leg);
+            if !completed {
+                return Ok(RouteExecution {
+                    legs,
+                    completed: false,
+                });
+            }
+        }
+        Ok(RouteExecution {
+            legs,
+            completed: true,
+        })
+    }
+
+    /// Leg amount and limit price at the precision of the market
+    ///
+    /// The amount is rounded down so a leg never spends more than the last one received.
+    fn round_leg(&self, hop: &Hop, amount: f32) -> Result<(f32, f32)> {
+        match self.markets {
+            Some(ref markets) => {
+                let order = markets.validate_order(
+                    &hop.edge.market,
+                    hop.order_type(),
+                    amount,
+                    hop.worst_price,
+                )?;
+                Ok((order.amount, order.price))
+            }
+            None => {
+                let rules = MarketRules::default();
+                Ok((
+                    floor_to(amount, rules.amount_decimals),
+                    round_to(hop.worst_price, rules.price_decimals),
+                ))
+            }
+        }
+    }
+
+    /// Last known fill of the order and whether it filled completely
+    ///
+    /// The remainder is cancelled when the order does not fill before the timeout.
+    fn wait_for_fill(
+        &self,
+        tracker: &mut OrderTracker,
+        order_id: u32,
+        timeout: Duration,
+    ) -> Result<(Option<Fill>, bool)> {
+        let started = Instant::now();
+        let mut fill = None;
+        loop {
+            for event in tracker.poll(self.client)? {
+                if let Some(done) = apply_event(event, &mut fill) {
+                    return Ok((fill, done));
+                }
+            }
+            if started.elapsed() >= timeout {
+                if order_id != 0 {
+                    self.client
+                        .cancel_order("Single".to_string(), Some(order_id), None)?;
+                    // Fills made between the last poll and the cancel
+                    for event in tracker.poll(self.client)? {
+                        if let Some(done) = apply_event(event, &mut fill) {
+                            return Ok((fill, done));
+                        }
+                    }
+                    tracker.forget(order_id);
+                }
+                return Ok((fill, false));
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+/// Fold an order event into the fill, returns whether the order filled completely once it is done
+fn apply_event(event: OrderEvent, fill: &mut Option<Fill>) -> Option<bool> {
+    match event {
+        OrderEvent::Filled {
+            filled, price, fee, ..
+        } => {
+            *fill = Some((filled, price, fee));
+            Some(true)
+        }
+        OrderEvent::PartiallyFilled {
+            filled, price, fee, ..
+        } => {
+            *fill = Some((filled, price, fee));
+            None
+        }
+        OrderEvent::Cancelled {
+            filled, price, fee, ..
+        } => {
+            if filled > 0.0 {
+                *fill = Some((filled, price, fee));
+            }
+            Some(false)
+        }
+    }
+}
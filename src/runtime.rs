@@ -0,0 +1,385 @@
+use signal_hook;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::Error;
+use orders::{OrderEvent, OrderTracker};
+use query::{Client, Result};
+use ratelimit::RateLimiter;
+use values::*;
+
+/// Unregisters the SIGINT handler when the runtime returns
+struct SignalGuard(signal_hook::SigId);
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        signal_hook::unregister(self.0);
+    }
+}
+
+/// Events delivered to a strategy by the runtime
+pub enum Event {
+    Ticker { market: String, ticker: Ticker },
+    OrderBook { market: String, book: PublicOrderBook },
+    /// An order showed up in `get_orders` for the first time
+    OrderOpened(Order),
+    /// An open order changed its remaining amount or status
+    OrderChanged(Order),
+    /// An order is no longer open, holds the last known state
+    OrderClosed(Order),
+    /// Fill or cancel of an order submitted through the context
+    Order(OrderEvent),
+    Timer(String),
+}
+
+/// What the runtime should do after a strategy saw an error
+pub enum ErrorAction {
+    Continue,
+    Stop,
+}
+
+/// Trading strategy driven by the runtime
+pub trait Strategy {
+    /// Markets to poll ticker (and order book) for e.g. 'LTC_BTC'
+    fn markets(&self) -> Vec<String>;
+
+    fn on_start(&mut self, _ctx: &mut Context) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_event(&mut self, ctx: &mut Context, event: &Event) -> Result<()>;
+
+    fn on_error(&mut self, _ctx: &mut Context, _error: &Error) -> ErrorAction {
+        ErrorAction::Continue
+    }
+
+    fn on_stop(&mut self, _ctx: &mut Context) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct Timer {
+    name: String,
+    every: Duration,
+    next: Instant,
+}
+
+/// Handle given to strategy callbacks, requests go through the runtime rate limiter
+pub struct Context<'a> {
+    client: &'a Client,
+    limiter: &'a RateLimiter,
+    tracker: &'a mut OrderTracker,
+    timers: &'a mut Vec<Timer>,
+    shutdown: &'a AtomicBool,
+}
+
+impl<'a> Context<'a> {
+    /// Direct access to the client (not rate limited)
+    pub fn client(&self) -> &Client {
+        self.client
+    }
+
+    /// Submit order, see `Client::submit_order`
+    ///
+    /// The order is tracked and its fills are delivered as `Event::Order`
+    pub fn submit_order(
+        &mut self,
+        market: String,
+        typeo: String,
+        amount: f32,
+        price: f32,
+    ) -> Result<SubmitOrder> {
+        if !self.tracker.is_primed() {
+            self.limiter.wait();
+            self.tracker.prime(self.client)?;
+        }
+        self.limiter.wait();
+        self.tracker.submit_order(self.client, market, typeo, amount, price)
+    }
+
+    /// Cancel order, see `Client::cancel_order`
+    pub fn cancel_order(
+        &mut self,
+        typeo: String,
+        orderid: Option<u32>,
+        market: Option<String>,
+    ) -> Result<CancelOrder> {
+        self.limiter.wait();
+        self.client.cancel_order(typeo, orderid, market)
+    }
+
+    /// Deliver `Event::Timer(name)` every interval, replaces a timer with the same name
+    pub fn schedule(&mut self, name: &str, every: Duration) {
+        self.cancel_timer(name);
+        self.timers.push(Timer {
+            name: name.to_string(),
+            every,
+            next: Instant::now() + every,
+        });
+    }
+
+    pub fn cancel_timer(&mut self, name: &str) {
+        self.timers.retain(|timer| timer.name != name);
+    }
+
+    /// Ask the runtime to shut down after the current event
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+}
+
+pub struct Runtime<'a> {
+    client: &'a Client,
+    limiter: RateLimiter,
+    tracker: OrderTracker,
+    poll_interval: Duration,
+    order_book_depth: Option<u32>,
+    order_count: u32,
+    cancel_on_shutdown: bool,
+    shutdown: Arc<AtomicBool>,
+    timers: Vec<Timer>,
+    open_orders: HashMap<u32, Order>,
+}
+
+impl<'a> Runtime<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Runtime {
+            client,
+            limiter: RateLimiter::new(1, Duration::from_millis(200)),
+            tracker: OrderTracker::new(),
+            poll_interval: Duration::from_secs(5),
+            order_book_depth: None,
+            order_count: 100,
+            cancel_on_shutdown: false,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            timers: Vec::new(),
+            open_orders: HashMap::new(),
+        }
+    }
+
+    /// Time between two polls of market data and orders (default: 5s)
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Limit requests made by the runtime and the strategy (default: 5 per second)
+    pub fn rate_limit(mut self, requests: u32, per: Duration) -> Self {
+        self.limiter = RateLimiter::new(requests, per);
+        self
+    }
+
+    /// Use a configured rate limiter instead of `rate_limit`, e.g. one reporting metrics
+    pub fn limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Also poll order books with the given depth (default: off)
+    pub fn order_book(mut self, depth: u32) -> Self {
+        self.order_book_depth = Some(depth);
+        self
+    }
+
+    /// Max open orders fetched on each poll (default: 100)
+    ///
+    /// While this many orders are open, `OrderClosed` is not reported.
+    pub fn order_count(mut self, order_count: u32) -> Self {
+        self.order_count = order_count;
+        self
+    }
+
+    /// Cancel all open orders when the runtime stops (default: false)
+    pub fn cancel_on_shutdown(mut self, cancel_on_shutdown: bool) -> Self {
+        self.cancel_on_shutdown = cancel_on_shutdown;
+        self
+    }
+
+    /// Flag that stops the runtime when set, also set on SIGINT
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shutdown)
+    }
+
+    /// Drive the strategy until shutdown is requested
+    pub fn run<S: Strategy>(mut self, strategy: &mut S) -> Result<()> {
+        let _sigint = SignalGuard(signal_hook::flag::register(
+            signal_hook::SIGINT,
+            Arc::clone(&self.shutdown),
+        )?);
+
+        let result = strategy.on_start(&mut self.context());
+        self.handle(strategy, result);
+
+        let mut next_poll = Instant::now();
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= next_poll {
+                self.poll(strategy);
+                next_poll = now + self.poll_interval;
+            }
+            self.fire_timers(strategy);
+            let wakeup = self.next_wakeup(next_poll);
+            self.sleep_until(wakeup);
+        }
+
+        let result = strategy.on_stop(&mut self.context());
+        self.handle(strategy, result);
+
+        if self.cancel_on_shutdown {
+            self.limiter.wait();
+            self.client.cancel_order("All".to_string(), None, None)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn context(&mut self) -> Context<'_> {
+        Context {
+            client: self.client,
+            limiter: &self.limiter,
+            tracker: &mut self.tracker,
+            timers: &mut self.timers,
+            shutdown: &self.shutdown,
+        }
+    }
+
+    fn handle<S: Strategy>(&mut self, strategy: &mut S, result: Result<()>) {
+        if let Err(error) = result {
+            if let ErrorAction::Stop = strategy.on_error(&mut self.context(), &error) {
+                self.shutdown.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub(crate) fn dispatch<S: Strategy>(&mut self, strategy: &mut S, event: Event) {
+        if self.shutdown.load(Ordering::SeqCst) {
+            return;
+        }
+        let result = strategy.on_event(&mut self.context(), &event);
+        self.handle(strategy, result);
+    }
+
+    fn poll<S: Strategy>(&mut self, strategy: &mut S) {
+        for market in strategy.markets() {
+            self.limiter.wait();
+            match self.client.get_ticker(market.clone()) {
+                Ok(ticker) => self.dispatch(
+                    strategy,
+                    Event::Ticker {
+                        market: market.clone(),
+                        ticker,
+                    },
+                ),
+                Err(error) => self.handle(strategy, Err(error)),
+            }
+
+            if let Some(depth) = self.order_book_depth {
+                self.limiter.wait();
+                match self.client.get_order_book(market.clone(), None, Some(depth)) {
+                    Ok(book) => self.dispatch(strategy, Event::OrderBook { market, book }),
+                    Err(error) => self.handle(strategy, Err(error)),
+                }
+            }
+        }
+
+        self.limiter.wait();
+        let orders = match self.client.get_orders(None, Some(self.order_count)) {
+            Ok(orders) => orders,
+            Err(error) => {
+                self.handle(strategy, Err(error));
+                return;
+            }
+        };
+        for event in self.diff_orders(&orders) {
+            self.dispatch(strategy, event);
+        }
+
+        if self.tracker.is_tracking() {
+            // The open orders above are reused, every request waits on the limiter
+            let client = self.client;
+            let limiter = &self.limiter;
+            let tracker = &mut self.tracker;
+            limiter.wait();
+            let events = tracker.fetch_trades(client).and_then(|trades| {
+                tracker.poll_with(&trades, &orders, |id| {
+                    limiter.wait();
+                    client.get_order(id)
+                })
+            });
+            match events {
+                Ok(events) => {
+                    for event in events {
+                        self.dispatch(strategy, Event::Order(event));
+                    }
+                }
+                Err(error) => self.handle(strategy, Err(error)),
+            }
+        }
+    }
+
+    pub(crate) fn diff_orders(&mut self, orders: &[Order]) -> Vec<Event> {
+        // A full page may leave out open orders, missing ones are only closed on a partial page
+        let complete = (orders.len() as u32) < self.order_count;
+        let mut events = Vec::new();
+        let mut open = HashMap::new();
+        for order in orders {
+            match self.open_orders.remove(&order.id) {
+                None => events.push(Event::OrderOpened(order.clone())),
+                Some(previous) => {
+                    if previous.remaining != order.remaining || previous.status != order.status {
+                        events.push(Event::OrderChanged(order.clone()));
+                    }
+                }
+            }
+            open.insert(order.id, order.clone());
+        }
+        for (id, order) in self.open_orders.drain() {
+            if complete {
+                events.push(Event::OrderClosed(order));
+            } else {
+                open.insert(id, order);
+            }
+        }
+        self.open_orders = open;
+        events
+    }
+
+    pub(crate) fn fire_timers<S: Strategy>(&mut self, strategy: &mut S) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for timer in self.timers.iter_mut() {
+            if timer.next <= now {
+                timer.next = now + timer.every;
+                due.push(timer.name.clone());
+            }
+        }
+        for name in due {
+            self.dispatch(strategy, Event::Timer(name));
+        }
+    }
+
+    /// Earliest of the next poll and the next timer deadline
+    pub(crate) fn next_wakeup(&self, next_poll: Instant) -> Instant {
+        self.timers
+            .iter()
+            .map(|timer| timer.next)
+            .fold(next_poll, |earliest, next| earliest.min(next))
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let step = Duration::from_millis(100);
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) {
+                return;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            thread::sleep(step.min(deadline - now));
+        }
+    }
+}
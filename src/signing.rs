@@ -0,0 +1,64 @@
+use base64::encode;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use url::form_urlencoded::byte_serialize;
+
+use std::fmt;
+
+use credentials::Credentials;
+
+/// Signed `Authorization` header of a private request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authorization {
+    pub api_key: String,
+    /// Base64 encoded HMAC-SHA512 of the string to sign
+    pub signature: String,
+    pub nonce: String,
+}
+
+impl fmt::Display for Authorization {
+    /// Header value: `Basic <api key>:<signature>:<nonce>`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Basic {}:{}:{}",
+            self.api_key, self.signature, self.nonce
+        )
+    }
+}
+
+/// String signed for a private request
+///
+/// api key + 'POST' + lowercased url-encoded url + nonce + base64 encoded body
+pub fn string_to_sign(api_key: &str, url: &str, body: &str, nonce: &str) -> String {
+    let url_encoded: String = byte_serialize(url.as_bytes()).collect();
+    format!(
+        "{}POST{}{}{}",
+        api_key,
+        url_encoded.to_lowercase(),
+        nonce,
+        encode(body)
+    )
+}
+
+/// Sign a private request
+///
+/// url: The full endpoint url e.g. 'https://tradesatoshi.com/api/private/getbalance' (required)
+/// body: The JSON body exactly as it is sent (required)
+/// nonce: The request nonce (required)
+pub fn sign_request(
+    credentials: &Credentials,
+    url: &str,
+    body: &str,
+    nonce: &str,
+) -> Authorization {
+    let api_key = credentials.api_key();
+    let mut mac =
+        Hmac::<Sha512>::new_varkey(credentials.secret()).expect("HMAC can take key of any size");
+    mac.input(string_to_sign(api_key, url, body, nonce).as_bytes());
+    Authorization {
+        api_key: api_key.to_string(),
+        signature: encode(&mac.result().code()),
+        nonce: nonce.to_string(),
+    }
+}
@@ -0,0 +1,521 @@
+use rusqlite::{params, Connection, Row, NO_PARAMS};
+
+use std::path::Path;
+
+use query::{Client, Result};
+use values::*;
+
+/// Schema versions, `PRAGMA user_version` is the number of migrations applied
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE trades (
+        id INTEGER PRIMARY KEY,
+        market TEXT NOT NULL,
+        type TEXT NOT NULL,
+        amount REAL NOT NULL,
+        rate REAL NOT NULL,
+        fee REAL NOT NULL,
+        total REAL NOT NULL,
+        timestamp TEXT NOT NULL,
+        is_api INTEGER NOT NULL
+    );
+    CREATE INDEX trades_market ON trades (market, timestamp);
+    CREATE TABLE deposits (
+        id TEXT PRIMARY KEY,
+        currency TEXT NOT NULL,
+        currency_long TEXT NOT NULL,
+        amount REAL NOT NULL,
+        fee REAL NOT NULL,
+        address TEXT NOT NULL,
+        status TEXT NOT NULL,
+        tx_id TEXT,
+        confirmations INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        is_api INTEGER NOT NULL
+    );
+    CREATE TABLE withdrawals (
+        id TEXT PRIMARY KEY,
+        currency TEXT NOT NULL,
+        currency_long TEXT NOT NULL,
+        amount REAL NOT NULL,
+        fee REAL NOT NULL,
+        address TEXT NOT NULL,
+        status TEXT NOT NULL,
+        tx_id TEXT,
+        confirmations INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        is_api INTEGER NOT NULL
+    );
+    CREATE TABLE orders (
+        id INTEGER PRIMARY KEY,
+        market TEXT NOT NULL,
+        type TEXT NOT NULL,
+        amount REAL NOT NULL,
+        rate REAL NOT NULL,
+        remaining REAL NOT NULL,
+        total REAL NOT NULL,
+        status TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        is_api INTEGER NOT NULL
+    );
+    CREATE TABLE market_history (
+        market TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        quantity REAL NOT NULL,
+        price REAL NOT NULL,
+        total REAL NOT NULL,
+        order_type TEXT NOT NULL,
+        PRIMARY KEY (market, id)
+    );
+    CREATE TABLE sync_state (
+        name TEXT PRIMARY KEY,
+        synced_at TEXT NOT NULL
+    );",
+];
+
+/// Statuses of orders that are no longer open
+const CLOSED_STATUSES: &[&str] = &[
+    "cancelled",
+    "canceled",
+    "closed",
+    "complete",
+    "completed",
+    "filled",
+];
+
+/// Requests made by `Store::sync`
+pub(crate) trait Source {
+    fn trade_history(&self, count: u32, page: u32) -> Result<Vec<TradeHistory>>;
+    fn deposits(&self, count: u32) -> Result<Vec<Transaction>>;
+    fn withdrawals(&self, count: u32) -> Result<Vec<Transaction>>;
+    fn orders(&self, count: u32) -> Result<Vec<Order>>;
+    fn order(&self, id: u32) -> Result<Order>;
+    fn market_history(&self, market: &str, count: u32) -> Result<Vec<Trade>>;
+}
+
+impl Source for Client {
+    fn trade_history(&self, count: u32, page: u32) -> Result<Vec<TradeHistory>> {
+        self.get_trade_history(None, Some(count), Some(page))
+    }
+
+    fn deposits(&self, count: u32) -> Result<Vec<Transaction>> {
+        self.get_deposits(None, Some(count))
+    }
+
+    fn withdrawals(&self, count: u32) -> Result<Vec<Transaction>> {
+        self.get_withdrawals(None, Some(count))
+    }
+
+    fn orders(&self, count: u32) -> Result<Vec<Order>> {
+        self.get_orders(None, Some(count))
+    }
+
+    fn order(&self, id: u32) -> Result<Order> {
+        self.get_order(id)
+    }
+
+    fn market_history(&self, market: &str, count: u32) -> Result<Vec<Trade>> {
+        self.get_market_history(market.to_string(), Some(count))
+    }
+}
+
+/// Rows added or changed by `Store::sync`
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    pub trades: usize,
+    pub deposits: usize,
+    pub withdrawals: usize,
+    pub orders: usize,
+    pub market_history: usize,
+}
+
+/// SQLite copy of the account history and market trades
+pub struct Store {
+    conn: Connection,
+    markets: Vec<String>,
+    count: u32,
+}
+
+impl Store {
+    /// Open or create the database and apply pending migrations
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Store::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Store::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        let mut store = Store {
+            conn,
+            markets: Vec::new(),
+            count: 100,
+        };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    /// Also sync the public trade history of a market e.g. 'LTC_BTC'
+    pub fn market(mut self, market: &str) -> Self {
+        self.markets.push(market.to_string());
+        self
+    }
+
+    /// Records fetched per request (default: 100)
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Number of migrations applied
+    pub fn schema_version(&self) -> Result<usize> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let version = self.schema_version()?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Fetch new and changed records, safe to call repeatedly
+    ///
+    /// A sync runs in one transaction, so a failed sync stores nothing and the next
+    /// one fetches the same range again. Trades are paged back to the newest trade
+    /// stored before, deposits and withdrawals are fetched in growing counts until a
+    /// stored one is included, as is the market history. Stored open orders missing
+    /// from `get_orders` are fetched with `get_order` to record how they closed. Only
+    /// added rows and status changes are counted.
+    pub fn sync(&mut self, client: &Client) -> Result<SyncReport> {
+        self.sync_from(client)
+    }
+
+    pub(crate) fn sync_from<S: Source>(&mut self, source: &S) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        let tx = self.conn.transaction()?;
+
+        let high_water: Option<u32> =
+            tx.query_row("SELECT MAX(id) FROM trades", NO_PARAMS, |row| row.get(0))?;
+        let mut page = 0;
+        loop {
+            let trades = source.trade_history(self.count, page)?;
+            let mut added = 0;
+            for trade in &trades {
+                added += tx.execute(
+                    "INSERT OR IGNORE INTO trades
+                     (id, market, type, amount, rate, fee, total, timestamp, is_api)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        trade.id,
+                        trade.market,
+                        trade.typeo,
+                        f64::from(trade.amount),
+                        f64::from(trade.rate),
+                        f64::from(trade.fee),
+                        f64::from(trade.total),
+                        trade.time_stamp,
+                        trade.is_api
+                    ],
+                )?;
+            }
+            report.trades += added;
+            // Newest first, older trades were stored by the last sync
+            let reached = match high_water {
+                Some(high_water) => trades.iter().any(|trade| trade.id <= high_water),
+                None => false,
+            };
+            // A page of stored trades only, e.g. the server ignored the page number
+            if reached || added == 0 || trades.len() < self.count as usize {
+                break;
+            }
+            page += 1;
+        }
+
+        report.deposits =
+            sync_transactions(&tx, "deposits", self.count, |count| source.deposits(count))?;
+        report.withdrawals = sync_transactions(&tx, "withdrawals", self.count, |count| {
+            source.withdrawals(count)
+        })?;
+
+        let mut orders = source.orders(self.count)?;
+        // Orders stored as open that closed since the last sync
+        let open: Vec<(u32, String)> = {
+            let mut stmt = tx.prepare("SELECT id, status FROM orders WHERE remaining > 0")?;
+            let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<::std::result::Result<_, _>>()?
+        };
+        for (id, status) in open {
+            let closed = CLOSED_STATUSES.contains(&status.to_lowercase().as_str());
+            if !closed && !orders.iter().any(|order| order.id == id) {
+                orders.push(source.order(id)?);
+            }
+        }
+        for order in orders {
+            let mut changed = tx.execute(
+                "INSERT OR IGNORE INTO orders
+                 (id, market, type, amount, rate, remaining, total, status, timestamp, is_api)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    order.id,
+                    order.market,
+                    order.order_type,
+                    f64::from(order.amount),
+                    f64::from(order.rate),
+                    f64::from(order.remaining),
+                    f64::from(order.total),
+                    order.status,
+                    order.timestamp,
+                    order.is_api
+                ],
+            )?;
+            if changed == 0 {
+                changed = tx.execute(
+                    "UPDATE orders SET remaining = ?2, total = ?3, status = ?4
+                     WHERE id = ?1 AND (remaining IS NOT ?2 OR total IS NOT ?3 OR status IS NOT ?4)",
+                    params![
+                        order.id,
+                        f64::from(order.remaining),
+                        f64::from(order.total),
+                        order.status
+                    ],
+                )?;
+            }
+            report.orders += changed;
+        }
+
+        for market in &self.markets {
+            // Growing counts until a stored trade is included, like transactions
+            let mut count = self.count.max(1);
+            let trades = loop {
+                let trades = source.market_history(market, count)?;
+                let mut reached = false;
+                for trade in &trades {
+                    let stored: i64 = tx.query_row(
+                        "SELECT COUNT(*) FROM market_history WHERE market = ?1 AND id = ?2",
+                        params![market, trade.id],
+                        |row| row.get(0),
+                    )?;
+                    reached |= stored > 0;
+                }
+                if reached || trades.len() < count as usize {
+                    break trades;
+                }
+                count = count.saturating_mul(2);
+            };
+            for trade in trades {
+                report.market_history += tx.execute(
+                    "INSERT OR IGNORE INTO market_history
+                     (market, id, timestamp, quantity, price, total, order_type)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        market,
+                        trade.id,
+                        trade.time_stamp,
+                        f64::from(trade.quantity),
+                        f64::from(trade.price),
+                        f64::from(trade.total),
+                        trade.order_type
+                    ],
+                )?;
+            }
+        }
+
+        tx.execute(
+            "INSERT OR REPLACE INTO sync_state (name, synced_at) VALUES ('account', ?1)",
+            params![::chrono::Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Time of the last successful `sync`, RFC 3339
+    pub fn last_sync(&self) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT synced_at FROM sync_state WHERE name = 'account'")?;
+        let mut rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Stored trades oldest first, all markets when None
+    pub fn trades(&self, market: Option<&str>) -> Result<Vec<TradeHistory>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, market, type, amount, rate, fee, total, timestamp, is_api FROM trades
+             WHERE ?1 IS NULL OR market = ?1 ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![market], |row| {
+            Ok(TradeHistory {
+                id: row.get(0)?,
+                market: row.get(1)?,
+                typeo: row.get(2)?,
+                amount: get_f32(row, 3)?,
+                rate: get_f32(row, 4)?,
+                fee: get_f32(row, 5)?,
+                total: get_f32(row, 6)?,
+                time_stamp: row.get(7)?,
+                is_api: row.get(8)?,
+            })
+        })?;
+        Ok(rows.collect::<::std::result::Result<_, _>>()?)
+    }
+
+    pub fn deposits(&self) -> Result<Vec<Transaction>> {
+        self.transactions("deposits")
+    }
+
+    pub fn withdrawals(&self) -> Result<Vec<Transaction>> {
+        self.transactions("withdrawals")
+    }
+
+    fn transactions(&self, table: &str) -> Result<Vec<Transaction>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, currency, currency_long, amount, fee, address, status, tx_id, confirmations, timestamp, is_api
+             FROM {} ORDER BY timestamp, id",
+            table
+        ))?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok(Transaction {
+                id: row.get(0)?,
+                currency: row.get(1)?,
+                currency_long: row.get(2)?,
+                amount: get_f32(row, 3)?,
+                fee: get_f32(row, 4)?,
+                address: row.get(5)?,
+                status: row.get(6)?,
+                tx_id: row.get(7)?,
+                confirmations: row.get(8)?,
+                time_stamp: row.get(9)?,
+                is_api: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<::std::result::Result<_, _>>()?)
+    }
+
+    /// Orders seen in `get_orders`, with their last known status
+    pub fn orders(&self) -> Result<Vec<Order>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, market, type, amount, rate, remaining, total, status, timestamp, is_api
+             FROM orders ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok(Order {
+                id: row.get(0)?,
+                market: row.get(1)?,
+                order_type: row.get(2)?,
+                amount: get_f32(row, 3)?,
+                rate: get_f32(row, 4)?,
+                remaining: get_f32(row, 5)?,
+                total: get_f32(row, 6)?,
+                status: row.get(7)?,
+                timestamp: row.get(8)?,
+                is_api: row.get(9)?,
+            })
+        })?;
+        Ok(rows.collect::<::std::result::Result<_, _>>()?)
+    }
+
+    /// Stored public trades of a market oldest first, e.g. for backtests
+    pub fn market_history(&self, market: &str) -> Result<Vec<Trade>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, quantity, price, total, order_type FROM market_history
+             WHERE market = ?1 ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![market], |row| {
+            Ok(Trade {
+                id: row.get(0)?,
+                time_stamp: row.get(1)?,
+                quantity: get_f32(row, 2)?,
+                price: get_f32(row, 3)?,
+                total: get_f32(row, 4)?,
+                order_type: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<::std::result::Result<_, _>>()?)
+    }
+}
+
+/// Fetch growing counts of transactions until a stored one is included, then store them
+///
+/// Returns the count of transactions added or changed.
+fn sync_transactions<F>(conn: &Connection, table: &str, count: u32, fetch: F) -> Result<usize>
+where
+    F: Fn(u32) -> Result<Vec<Transaction>>,
+{
+    let mut count = count.max(1);
+    let transactions = loop {
+        let transactions = fetch(count)?;
+        let mut reached = false;
+        for transaction in &transactions {
+            let stored: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE id = ?1", table),
+                params![transaction.id],
+                |row| row.get(0),
+            )?;
+            reached |= stored > 0;
+        }
+        if reached || transactions.len() < count as usize {
+            break transactions;
+        }
+        count = count.saturating_mul(2);
+    };
+
+    let insert = format!(
+        "INSERT OR IGNORE INTO {}
+         (id, currency, currency_long, amount, fee, address, status, tx_id, confirmations, timestamp, is_api)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        table
+    );
+    let update = format!(
+        "UPDATE {} SET fee = ?2, status = ?3, tx_id = ?4, confirmations = ?5
+         WHERE id = ?1 AND (fee IS NOT ?2 OR status IS NOT ?3 OR tx_id IS NOT ?4 OR confirmations IS NOT ?5)",
+        table
+    );
+    let mut changed = 0;
+    for transaction in &transactions {
+        let inserted = conn.execute(
+            &insert,
+            params![
+                transaction.id,
+                transaction.currency,
+                transaction.currency_long,
+                f64::from(transaction.amount),
+                f64::from(transaction.fee),
+                transaction.address,
+                transaction.status,
+                transaction.tx_id,
+                transaction.confirmations,
+                transaction.time_stamp,
+                transaction.is_api
+            ],
+        )?;
+        changed += if inserted > 0 {
+            inserted
+        } else {
+            conn.execute(
+                &update,
+                params![
+                    transaction.id,
+                    f64::from(transaction.fee),
+                    transaction.status,
+                    transaction.tx_id,
+                    transaction.confirmations
+                ],
+            )?
+        };
+    }
+    Ok(changed)
+}
+
+/// SQLite stores REAL as f64
+fn get_f32(row: &Row, index: usize) -> ::rusqlite::Result<f32> {
+    row.get::<_, f64>(index).map(|value| value as f32)
+}
@@ -0,0 +1,363 @@
+use chrono::{DateTime, Utc};
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use markets::split_market;
+use values::*;
+
+const EPSILON: f32 = 1e-8;
+
+/// How disposals are matched against acquired lots
+#[derive(Debug, Clone)]
+pub enum LotMatching {
+    /// Oldest lots first
+    Fifo,
+    /// Lots chosen per disposal id e.g. "trade:42" -> [("deposit:abc", 0.5)]
+    ///
+    /// Amounts not covered by the selection are matched FIFO.
+    SpecificId(HashMap<String, Vec<(String, f32)>>),
+}
+
+/// Acquired amount not disposed yet, costs are in the reference currency
+#[derive(Debug, Clone)]
+pub struct TaxLot {
+    /// Source of the lot e.g. "trade:42" or "deposit:abc"
+    pub id: String,
+    pub currency: String,
+    pub acquired: DateTime<Utc>,
+    pub amount: f32,
+    /// Cost basis per unit
+    pub price: f32,
+}
+
+/// Capital gain of a disposed amount matched to one lot
+#[derive(Debug, Clone)]
+pub struct GainLine {
+    pub currency: String,
+    pub amount: f32,
+    /// None when no lot was left to match, the cost basis is then zero
+    pub acquired: Option<DateTime<Utc>>,
+    pub disposed: DateTime<Utc>,
+    /// None when the disposal could not be valued, it is then listed in `unpriced`
+    pub proceeds: Option<f32>,
+    pub cost_basis: f32,
+    pub gain: Option<f32>,
+    pub lot_id: Option<String>,
+    pub disposal_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaxReport {
+    pub reference: String,
+    pub lines: Vec<GainLine>,
+    /// Lots still held at the end of the history
+    pub open_lots: Vec<TaxLot>,
+    /// Events without a valuation, acquired lots have zero cost and disposals unknown proceeds
+    pub unpriced: Vec<String>,
+}
+
+impl TaxReport {
+    /// Sum of the gains known, lines with unknown proceeds are left out
+    pub fn total_gain(&self) -> f32 {
+        self.lines.iter().filter_map(|line| line.gain).sum()
+    }
+
+    /// Write one CSV row per gain line with a header row
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "currency,amount,acquired,disposed,proceeds,cost_basis,gain,lot_id,disposal_id"
+        )?;
+        for line in &self.lines {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                csv_field(&line.currency),
+                line.amount,
+                line.acquired.map(format_date).unwrap_or_default(),
+                format_date(line.disposed),
+                optional(line.proceeds),
+                line.cost_basis,
+                optional(line.gain),
+                line.lot_id.as_ref().map(|id| csv_field(id)).unwrap_or_default(),
+                csv_field(&line.disposal_id)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_date(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Empty field for an unknown value
+fn optional(value: Option<f32>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Balance change from one history record
+struct Flow {
+    id: String,
+    time: DateTime<Utc>,
+    disposed: Option<(String, f32)>,
+    acquired: Option<(String, f32)>,
+    /// Withdrawal to an own wallet, lots leave without a gain
+    transfer: bool,
+}
+
+/// Builds capital gains reports from trade, deposit and withdrawal history
+pub struct CapitalGains {
+    reference: String,
+    matching: LotMatching,
+    withdrawals_as_disposals: bool,
+}
+
+impl CapitalGains {
+    /// reference: Currency gains are reported in, e.g. 'USD' or 'BTC' (required)
+    pub fn new(reference: &str) -> Self {
+        CapitalGains {
+            reference: reference.to_string(),
+            matching: LotMatching::Fifo,
+            withdrawals_as_disposals: false,
+        }
+    }
+
+    /// Lot matching (default: Fifo)
+    pub fn matching(mut self, matching: LotMatching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// Report withdrawals as disposals instead of transfers (default: false)
+    pub fn withdrawals_as_disposals(mut self, withdrawals_as_disposals: bool) -> Self {
+        self.withdrawals_as_disposals = withdrawals_as_disposals;
+        self
+    }
+
+    /// Match the history and report a gain line per disposed lot
+    ///
+    /// price: Value of one unit of a currency in the reference currency at a time (required)
+    pub fn report<F>(
+        &self,
+        trades: &[TradeHistory],
+        deposits: &[Transaction],
+        withdrawals: &[Transaction],
+        price: F,
+    ) -> TaxReport
+    where
+        F: Fn(&str, DateTime<Utc>) -> Option<f32>,
+    {
+        let mut report = TaxReport {
+            reference: self.reference.clone(),
+            lines: Vec::new(),
+            open_lots: Vec::new(),
+            unpriced: Vec::new(),
+        };
+        let mut lots: HashMap<String, Vec<TaxLot>> = HashMap::new();
+
+        for flow in self.flows(trades, deposits, withdrawals, &mut report.unpriced) {
+            let value = self.value(&flow, &price);
+            if value.is_none() {
+                report.unpriced.push(flow.id.clone());
+            }
+
+            if let Some((ref currency, amount)) = flow.disposed {
+                if *currency != self.reference {
+                    let matched = self.match_lots(
+                        lots.entry(currency.clone()).or_default(),
+                        &flow.id,
+                        amount,
+                    );
+                    if !flow.transfer {
+                        for (lot, quantity) in matched {
+                            let proceeds = value.map(|value| value * quantity / amount);
+                            let cost_basis = lot.as_ref().map_or(0.0, |lot| lot.price * quantity);
+                            report.lines.push(GainLine {
+                                currency: currency.clone(),
+                                amount: quantity,
+                                acquired: lot.as_ref().map(|lot| lot.acquired),
+                                disposed: flow.time,
+                                proceeds,
+                                cost_basis,
+                                gain: proceeds.map(|proceeds| proceeds - cost_basis),
+                                lot_id: lot.map(|lot| lot.id),
+                                disposal_id: flow.id.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+
+            if let Some((ref currency, amount)) = flow.acquired {
+                if *currency != self.reference && amount > EPSILON {
+                    lots.entry(currency.clone()).or_default().push(TaxLot {
+                        id: flow.id.clone(),
+                        currency: currency.clone(),
+                        acquired: flow.time,
+                        amount,
+                        price: value.unwrap_or(0.0) / amount,
+                    });
+                }
+            }
+        }
+
+        let mut open_lots: Vec<TaxLot> = lots.drain().flat_map(|(_, lots)| lots).collect();
+        open_lots.sort_by_key(|lot| lot.acquired);
+        report.open_lots = open_lots;
+        report
+    }
+
+    /// History as balance changes, oldest first
+    fn flows(
+        &self,
+        trades: &[TradeHistory],
+        deposits: &[Transaction],
+        withdrawals: &[Transaction],
+        unpriced: &mut Vec<String>,
+    ) -> Vec<Flow> {
+        let mut flows = Vec::new();
+        for trade in trades {
+            let id = format!("trade:{}", trade.id);
+            let (time, (base, quote)) = match (
+                parse_timestamp(&trade.time_stamp),
+                split_market(&trade.market),
+            ) {
+                (Some(time), Some(currencies)) => (time, currencies),
+                _ => {
+                    unpriced.push(id);
+                    continue;
+                }
+            };
+            // Fees are charged in quote currency
+            let (disposed, acquired) = if trade.typeo.eq_ignore_ascii_case("buy") {
+                (
+                    (quote, trade.amount * trade.rate + trade.fee),
+                    (base, trade.amount),
+                )
+            } else {
+                (
+                    (base, trade.amount),
+                    (quote, trade.amount * trade.rate - trade.fee),
+                )
+            };
+            flows.push(Flow {
+                id,
+                time,
+                disposed: Some((disposed.0.to_string(), disposed.1)),
+                acquired: Some((acquired.0.to_string(), acquired.1)),
+                transfer: false,
+            });
+        }
+        for deposit in deposits.iter().filter(|deposit| !failed(deposit)) {
+            let id = format!("deposit:{}", deposit.id);
+            match parse_timestamp(&deposit.time_stamp) {
+                Some(time) => flows.push(Flow {
+                    id,
+                    time,
+                    disposed: None,
+                    acquired: Some((deposit.currency.clone(), deposit.amount)),
+                    transfer: false,
+                }),
+                None => unpriced.push(id),
+            }
+        }
+        for withdrawal in withdrawals.iter().filter(|withdrawal| !failed(withdrawal)) {
+            let id = format!("withdrawal:{}", withdrawal.id);
+            match parse_timestamp(&withdrawal.time_stamp) {
+                Some(time) => flows.push(Flow {
+                    id,
+                    time,
+                    disposed: Some((
+                        withdrawal.currency.clone(),
+                        withdrawal.amount + withdrawal.fee,
+                    )),
+                    acquired: None,
+                    transfer: !self.withdrawals_as_disposals,
+                }),
+                None => unpriced.push(id),
+            }
+        }
+        flows.sort_by_key(|flow| flow.time);
+        flows
+    }
+
+    /// Value of the flow in the reference currency, by what was received when priced
+    fn value<F>(&self, flow: &Flow, price: &F) -> Option<f32>
+    where
+        F: Fn(&str, DateTime<Utc>) -> Option<f32>,
+    {
+        let value_of = |&(ref currency, amount): &(String, f32)| {
+            if *currency == self.reference {
+                Some(amount)
+            } else {
+                price(currency, flow.time).map(|price| price * amount)
+            }
+        };
+        flow.acquired
+            .as_ref()
+            .and_then(&value_of)
+            .or_else(|| flow.disposed.as_ref().and_then(&value_of))
+    }
+
+    /// Take `amount` off the lots, a None lot covers the amount no lot was left for
+    fn match_lots(
+        &self,
+        lots: &mut Vec<TaxLot>,
+        disposal_id: &str,
+        amount: f32,
+    ) -> Vec<(Option<TaxLot>, f32)> {
+        let mut matched = Vec::new();
+        let mut remaining = amount;
+
+        if let LotMatching::SpecificId(ref selections) = self.matching {
+            for &(ref lot_id, selected) in selections.get(disposal_id).into_iter().flatten() {
+                if let Some(index) = lots.iter().position(|lot| lot.id == *lot_id) {
+                    let quantity = take_lot(lots, index, selected.min(remaining), &mut matched);
+                    remaining -= quantity;
+                }
+            }
+        }
+        while remaining > EPSILON && !lots.is_empty() {
+            let quantity = take_lot(lots, 0, remaining, &mut matched);
+            remaining -= quantity;
+        }
+        if remaining > EPSILON {
+            matched.push((None, remaining));
+        }
+        matched
+    }
+}
+
+/// Take up to `amount` off a lot, returns the amount taken
+fn take_lot(
+    lots: &mut Vec<TaxLot>,
+    index: usize,
+    amount: f32,
+    matched: &mut Vec<(Option<TaxLot>, f32)>,
+) -> f32 {
+    let quantity = amount.min(lots[index].amount);
+    if quantity <= 0.0 {
+        return 0.0;
+    }
+    matched.push((Some(lots[index].clone()), quantity));
+    lots[index].amount -= quantity;
+    if lots[index].amount <= EPSILON {
+        lots.remove(index);
+    }
+    quantity
+}
+
+fn failed(transaction: &Transaction) -> bool {
+    let status = transaction.status.to_lowercase();
+    status.contains("cancel") || status.contains("fail") || status.contains("reject")
+}
@@ -0,0 +1,184 @@
+use std::ops::AddAssign;
+
+use graph::{Edge, MarketGraph};
+use query::{Client, Result};
+use values::*;
+
+/// Market price used to convert between currencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceSource {
+    Bid,
+    Ask,
+    Last,
+    /// Middle of bid and ask
+    Mid,
+}
+
+impl PriceSource {
+    pub fn price(&self, summary: &MarketSummary) -> f32 {
+        match *self {
+            PriceSource::Bid => summary.bid,
+            PriceSource::Ask => summary.ask,
+            PriceSource::Last => summary.last,
+            PriceSource::Mid => (summary.bid + summary.ask) / 2.0,
+        }
+    }
+}
+
+/// Balance amounts split like `Balance`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Amounts {
+    pub available: f32,
+    pub held_for_trades: f32,
+    pub unconfirmed: f32,
+    pub pending_withdraw: f32,
+    pub total: f32,
+}
+
+impl Amounts {
+    fn from_balance(balance: &Balance) -> Self {
+        Amounts {
+            available: balance.available,
+            held_for_trades: balance.held_for_trades,
+            unconfirmed: balance.unconfirmed,
+            pending_withdraw: balance.pending_withdraw,
+            total: balance.total,
+        }
+    }
+
+    fn scale(&self, rate: f32) -> Self {
+        Amounts {
+            available: self.available * rate,
+            held_for_trades: self.held_for_trades * rate,
+            unconfirmed: self.unconfirmed * rate,
+            pending_withdraw: self.pending_withdraw * rate,
+            total: self.total * rate,
+        }
+    }
+}
+
+impl AddAssign for Amounts {
+    fn add_assign(&mut self, other: Amounts) {
+        self.available += other.available;
+        self.held_for_trades += other.held_for_trades;
+        self.unconfirmed += other.unconfirmed;
+        self.pending_withdraw += other.pending_withdraw;
+        self.total += other.total;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BalanceValue {
+    pub currency: String,
+    /// Price of one unit in the reference currency
+    pub rate: f32,
+    /// Markets used to price the currency
+    pub route: Vec<String>,
+    /// Amounts in the currency itself
+    pub amounts: Amounts,
+    /// Amounts in the reference currency
+    pub value: Amounts,
+}
+
+#[derive(Debug, Clone)]
+pub struct Valuation {
+    pub reference: String,
+    pub balances: Vec<BalanceValue>,
+    /// Currencies with a balance but no route to the reference currency
+    pub unpriced: Vec<String>,
+    pub totals: Amounts,
+}
+
+/// Prices balances in a reference currency e.g. 'BTC' or 'USDT'
+pub struct Valuer {
+    reference: String,
+    source: PriceSource,
+    max_hops: usize,
+}
+
+impl Valuer {
+    pub fn new(reference: &str) -> Self {
+        Valuer {
+            reference: reference.to_string(),
+            source: PriceSource::Last,
+            max_hops: 3,
+        }
+    }
+
+    /// Price used for every market on the route (default: Last)
+    pub fn price_source(mut self, source: PriceSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Max markets on a route to the reference currency (default: 3)
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Value all balances from `get_balances` with prices from `get_market_summaries`
+    pub fn value(&self, client: &Client) -> Result<Valuation> {
+        let balances = client.get_balances()?;
+        let graph = MarketGraph::from_client(client)?;
+        Ok(self.value_balances(&balances, &graph))
+    }
+
+    pub fn value_balances(&self, balances: &[Balance], graph: &MarketGraph) -> Valuation {
+        let mut valuation = Valuation {
+            reference: self.reference.clone(),
+            balances: Vec::new(),
+            unpriced: Vec::new(),
+            totals: Amounts::default(),
+        };
+        for balance in balances {
+            if balance.total == 0.0 {
+                continue;
+            }
+            let (path, rate) = match self.route(graph, &balance.currency) {
+                Some(priced) => priced,
+                None => {
+                    valuation.unpriced.push(balance.currency.clone());
+                    continue;
+                }
+            };
+            let amounts = Amounts::from_balance(balance);
+            let value = amounts.scale(rate);
+            valuation.totals += value;
+            valuation.balances.push(BalanceValue {
+                currency: balance.currency.clone(),
+                rate,
+                route: path.into_iter().map(|edge| edge.market).collect(),
+                amounts,
+                value,
+            });
+        }
+        valuation
+    }
+
+    /// Route with the fewest hops where every market has a price, and its rate
+    ///
+    /// Markets without a usable price are left out of the search.
+    pub fn route(&self, graph: &MarketGraph, currency: &str) -> Option<(Vec<Edge>, f32)> {
+        let path = graph.shortest_path_by(currency, &self.reference, self.max_hops, |edge| {
+            self.edge_rate(graph, edge).is_some()
+        })?;
+        let rate = self.route_rate(graph, &path)?;
+        Some((path, rate))
+    }
+
+    /// Price of one unit of the start currency at the end of the path
+    pub fn route_rate(&self, graph: &MarketGraph, path: &[Edge]) -> Option<f32> {
+        path.iter()
+            .try_fold(1.0, |rate, edge| Some(rate * self.edge_rate(graph, edge)?))
+    }
+
+    /// Units of `edge.to` per unit of `edge.from`, None without a usable price
+    fn edge_rate(&self, graph: &MarketGraph, edge: &Edge) -> Option<f32> {
+        let price = self.source.price(graph.summary(&edge.market)?);
+        if price <= 0.0 || !price.is_finite() {
+            return None;
+        }
+        Some(if edge.sell { price } else { 1.0 / price })
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
 #[derive(Serialize, Deserialize)]
 pub struct APIResult<T> {
     pub success: bool,
@@ -12,7 +14,7 @@ pub struct APIVecResult<T> {
     pub result: Option<Vec<T>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Currency {
     pub currency: String,
     #[serde(rename = "currencyLong")]
@@ -30,7 +32,7 @@ pub struct Address {
     pub address: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Balance {
     pub currency: String,
     #[serde(rename = "currencyLong")]
@@ -45,9 +47,9 @@ pub struct Balance {
     pub address: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Order {
-    pub id: bool,
+    pub id: u32,
     pub market: String,
     // type is a reserved keyword
     #[serde(rename = "type")]
@@ -62,7 +64,7 @@ pub struct Order {
     pub is_api: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MarketSummary {
     pub market: String,
     pub high: f32,
@@ -73,32 +75,35 @@ pub struct MarketSummary {
     pub base_volume: f32,
     pub bid: f32,
     pub ask: f32,
+    /// Price change over 24 hours in percent
+    #[serde(default)]
+    pub change: f32,
     #[serde(rename = "openBuyOrders")]
     pub open_buy_orders: u32,
     #[serde(rename = "openSellOrders")]
     pub open_sell_orders: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Ticker {
     pub ask: f32,
     pub bid: f32,
     pub last: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PublicOrderBook {
     pub buy: Vec<PublicOrder>,
     pub sell: Vec<PublicOrder>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PublicOrder {
     pub quantity: f32,
     pub rate: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Trade {
     pub id: u32,
     #[serde(rename = "timeStamp")]
@@ -110,7 +115,7 @@ pub struct Trade {
     pub order_type: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TradeHistory {
     pub id: u32,
     pub market: String,
@@ -168,3 +173,11 @@ pub struct CancelOrder {
 pub struct SubmitTransfer {
     pub data: String,
 }
+
+/// Parse an API timestamp e.g. '2016-04-06T18:26:09.447' (UTC)
+pub fn parse_timestamp(time_stamp: &str) -> Option<DateTime<Utc>> {
+    let time_stamp = time_stamp.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(time_stamp, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|time| Utc.from_utc_datetime(&time))
+}
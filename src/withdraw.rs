@@ -0,0 +1,416 @@
+use rand;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use address::validate_address;
+use query::{Client, Result};
+use values::*;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// A withdrawal or transfer waiting for confirmation
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawAction {
+    Withdraw {
+        currency: String,
+        address: String,
+        amount: f32,
+    },
+    Transfer {
+        currency: String,
+        username: String,
+        amount: f32,
+    },
+}
+
+impl WithdrawAction {
+    fn currency(&self) -> &str {
+        match *self {
+            WithdrawAction::Withdraw { ref currency, .. } => currency,
+            WithdrawAction::Transfer { ref currency, .. } => currency,
+        }
+    }
+
+    fn amount(&self) -> f32 {
+        match *self {
+            WithdrawAction::Withdraw { amount, .. } => amount,
+            WithdrawAction::Transfer { amount, .. } => amount,
+        }
+    }
+}
+
+impl fmt::Display for WithdrawAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WithdrawAction::Withdraw {
+                ref currency,
+                ref address,
+                amount,
+            } => write!(f, "Withdraw {} {} to address {}", amount, currency, address),
+            WithdrawAction::Transfer {
+                ref currency,
+                ref username,
+                amount,
+            } => write!(f, "Transfer {} {} to user {}", amount, currency, username),
+        }
+    }
+}
+
+/// Token returned by `prepare_withdraw`/`prepare_transfer`
+#[derive(Debug, Clone)]
+pub struct WithdrawToken {
+    pub id: String,
+    pub action: WithdrawAction,
+    pub expires: SystemTime,
+}
+
+impl fmt::Display for WithdrawToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.id, self.action)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawRejection {
+    AddressNotAllowed { currency: String, address: String },
+    UsernameNotAllowed(String),
+    /// The allowlist entry was added less than the cooldown ago
+    CoolingDown { remaining: Duration },
+    TransactionLimit { amount: f32, limit: f32 },
+    DailyLimit { total: f32, limit: f32 },
+    InvalidAmount(f32),
+    UnknownToken(String),
+    TokenExpired(String),
+    /// A withdrawal token was confirmed as transfer or the other way around
+    WrongTokenKind(String),
+}
+
+impl fmt::Display for WithdrawRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WithdrawRejection::AddressNotAllowed {
+                ref currency,
+                ref address,
+            } => write!(f, "Address {} is not allowed for {}", address, currency),
+            WithdrawRejection::UsernameNotAllowed(ref username) => {
+                write!(f, "Username {} is not allowed", username)
+            }
+            WithdrawRejection::CoolingDown { remaining } => write!(
+                f,
+                "Allowlist entry is cooling down for {}s",
+                remaining.as_secs()
+            ),
+            WithdrawRejection::TransactionLimit { amount, limit } => {
+                write!(f, "Amount {} exceeds per transaction limit {}", amount, limit)
+            }
+            WithdrawRejection::DailyLimit { total, limit } => {
+                write!(f, "Daily total {} would exceed limit {}", total, limit)
+            }
+            WithdrawRejection::InvalidAmount(amount) => write!(f, "Invalid amount {}", amount),
+            WithdrawRejection::UnknownToken(ref id) => write!(f, "Unknown token {}", id),
+            WithdrawRejection::TokenExpired(ref id) => write!(f, "Token {} expired", id),
+            WithdrawRejection::WrongTokenKind(ref id) => {
+                write!(f, "Token {} is for a different action", id)
+            }
+        }
+    }
+}
+
+/// Per currency withdrawal caps
+#[derive(Debug, Clone, Default)]
+pub struct WithdrawLimits {
+    pub per_transaction: Option<f32>,
+    pub per_day: Option<f32>,
+}
+
+/// Opt-in safety layer for `submit_withdraw` and `submit_transfer`
+///
+/// Destinations must be on an allowlist for at least the cooldown, amounts are
+/// capped and every action has to be prepared and then confirmed with its token.
+///
+/// All state lives in memory only: allowlist entries, tokens and the actions counted
+/// against the daily caps are lost with the guard. A new guard starts with unused
+/// daily caps unless the actions of the last day are replayed with `record_executed`.
+pub struct WithdrawGuard<'a> {
+    client: &'a Client,
+    addresses: HashMap<String, HashMap<String, SystemTime>>,
+    usernames: HashMap<String, SystemTime>,
+    limits: HashMap<String, WithdrawLimits>,
+    cooldown: Duration,
+    token_ttl: Duration,
+    pending: HashMap<String, WithdrawToken>,
+    /// (time, currency, amount) of executed actions
+    executed: Vec<(SystemTime, String, f32)>,
+}
+
+impl<'a> WithdrawGuard<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        WithdrawGuard {
+            client,
+            addresses: HashMap::new(),
+            usernames: HashMap::new(),
+            limits: HashMap::new(),
+            cooldown: Duration::from_secs(DAY),
+            token_ttl: Duration::from_secs(5 * 60),
+            pending: HashMap::new(),
+            executed: Vec::new(),
+        }
+    }
+
+    /// Time a new allowlist entry has to wait before it can be used (default: 24h)
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Time a prepared token stays valid (default: 5min)
+    pub fn token_ttl(mut self, token_ttl: Duration) -> Self {
+        self.token_ttl = token_ttl;
+        self
+    }
+
+    /// Caps for a currency e.g. 'BTC'
+    pub fn set_limits(&mut self, currency: &str, limits: WithdrawLimits) {
+        self.limits.insert(currency.to_string(), limits);
+    }
+
+    /// Add an address to the allowlist, the address has to be valid for the currency
+    pub fn allow_address(&mut self, currency: &str, address: &str) -> Result<()> {
+        validate_address(currency, address)?;
+        self.addresses
+            .entry(currency.to_string())
+            .or_default()
+            .insert(address.to_string(), SystemTime::now());
+        Ok(())
+    }
+
+    /// Remove an address from the allowlist, pending withdrawals to it are dropped
+    pub fn remove_address(&mut self, currency: &str, address: &str) {
+        if let Some(addresses) = self.addresses.get_mut(currency) {
+            addresses.remove(address);
+        }
+        self.pending.retain(|_, token| match token.action {
+            WithdrawAction::Withdraw {
+                currency: ref pending_currency,
+                address: ref pending_address,
+                ..
+            } => pending_currency != currency || pending_address != address,
+            WithdrawAction::Transfer { .. } => true,
+        });
+    }
+
+    pub fn allow_username(&mut self, username: &str) {
+        self.usernames.insert(username.to_string(), SystemTime::now());
+    }
+
+    /// Remove a username from the allowlist, pending transfers to it are dropped
+    pub fn remove_username(&mut self, username: &str) {
+        self.usernames.remove(username);
+        self.pending.retain(|_, token| match token.action {
+            WithdrawAction::Transfer {
+                username: ref pending_username,
+                ..
+            } => pending_username != username,
+            WithdrawAction::Withdraw { .. } => true,
+        });
+    }
+
+    /// Check a withdrawal and return a token describing it
+    ///
+    /// currency: The currency name e.g. 'BTC' (required)
+    /// address: The receiving address (required)
+    /// amount: The amount to withdraw (required)
+    pub fn prepare_withdraw(
+        &mut self,
+        currency: &str,
+        address: &str,
+        amount: f32,
+    ) -> Result<WithdrawToken> {
+        self.prepare(WithdrawAction::Withdraw {
+            currency: currency.to_string(),
+            address: address.to_string(),
+            amount,
+        })
+    }
+
+    /// Check a transfer and return a token describing it
+    ///
+    /// currency: The currency name e.g. 'BTC' (required)
+    /// username: The TradeSatoshi username to transfer the funds to (required)
+    /// amount: The amount of coin to transfer (required)
+    pub fn prepare_transfer(
+        &mut self,
+        currency: &str,
+        username: &str,
+        amount: f32,
+    ) -> Result<WithdrawToken> {
+        self.prepare(WithdrawAction::Transfer {
+            currency: currency.to_string(),
+            username: username.to_string(),
+            amount,
+        })
+    }
+
+    /// Execute a prepared withdrawal
+    pub fn confirm_withdraw(&mut self, token_id: &str) -> Result<Id> {
+        let token = self.take_token(token_id)?;
+        match token.action {
+            WithdrawAction::Withdraw {
+                currency,
+                address,
+                amount,
+            } => {
+                let id = self
+                    .client
+                    .submit_withdraw(currency.clone(), address, amount)?;
+                self.executed.push((SystemTime::now(), currency, amount));
+                Ok(id)
+            }
+            WithdrawAction::Transfer { .. } => {
+                self.pending.insert(token.id.clone(), token);
+                Err(WithdrawRejection::WrongTokenKind(token_id.to_string()).into())
+            }
+        }
+    }
+
+    /// Execute a prepared transfer
+    pub fn confirm_transfer(&mut self, token_id: &str) -> Result<SubmitTransfer> {
+        let token = self.take_token(token_id)?;
+        match token.action {
+            WithdrawAction::Transfer {
+                currency,
+                username,
+                amount,
+            } => {
+                let transfer = self
+                    .client
+                    .submit_transfer(currency.clone(), username, amount)?;
+                self.executed.push((SystemTime::now(), currency, amount));
+                Ok(transfer)
+            }
+            WithdrawAction::Withdraw { .. } => {
+                self.pending.insert(token.id.clone(), token);
+                Err(WithdrawRejection::WrongTokenKind(token_id.to_string()).into())
+            }
+        }
+    }
+
+    /// Count an action executed elsewhere against the daily cap, e.g. one from before a restart
+    pub fn record_executed(&mut self, time: SystemTime, currency: &str, amount: f32) {
+        self.executed.push((time, currency.to_string(), amount));
+    }
+
+    /// Drop a prepared action without executing it
+    pub fn cancel(&mut self, token_id: &str) -> Option<WithdrawToken> {
+        self.purge_expired();
+        self.pending.remove(token_id)
+    }
+
+    /// Actions waiting for confirmation, expired tokens are left out
+    pub fn pending(&self) -> Vec<&WithdrawToken> {
+        let now = SystemTime::now();
+        self.pending
+            .values()
+            .filter(|token| token.expires >= now)
+            .collect()
+    }
+
+    fn purge_expired(&mut self) {
+        let now = SystemTime::now();
+        self.pending.retain(|_, token| token.expires >= now);
+    }
+
+    fn prepare(&mut self, action: WithdrawAction) -> Result<WithdrawToken> {
+        self.purge_expired();
+        self.check_destination(&action)?;
+        self.check_limits(action.currency(), action.amount())?;
+
+        let token = WithdrawToken {
+            id: format!("{:016x}", rand::random::<u64>()),
+            action,
+            expires: SystemTime::now() + self.token_ttl,
+        };
+        self.pending.insert(token.id.clone(), token.clone());
+        Ok(token)
+    }
+
+    fn take_token(&mut self, token_id: &str) -> Result<WithdrawToken> {
+        let token = self
+            .pending
+            .remove(token_id)
+            .ok_or_else(|| WithdrawRejection::UnknownToken(token_id.to_string()))?;
+        if token.expires < SystemTime::now() {
+            return Err(WithdrawRejection::TokenExpired(token.id).into());
+        }
+        self.purge_expired();
+        // The destination may have been removed and limits used up since preparing
+        self.check_destination(&token.action)?;
+        self.check_limits(token.action.currency(), token.action.amount())?;
+        Ok(token)
+    }
+
+    /// The destination has to be on the allowlist for at least the cooldown
+    fn check_destination(&self, action: &WithdrawAction) -> Result<()> {
+        let added = match *action {
+            WithdrawAction::Withdraw {
+                ref currency,
+                ref address,
+                ..
+            } => self
+                .addresses
+                .get(currency)
+                .and_then(|addresses| addresses.get(address))
+                .cloned()
+                .ok_or_else(|| WithdrawRejection::AddressNotAllowed {
+                    currency: currency.to_string(),
+                    address: address.to_string(),
+                })?,
+            WithdrawAction::Transfer { ref username, .. } => self
+                .usernames
+                .get(username)
+                .cloned()
+                .ok_or_else(|| WithdrawRejection::UsernameNotAllowed(username.to_string()))?,
+        };
+        let age = SystemTime::now()
+            .duration_since(added)
+            .unwrap_or(Duration::from_secs(0));
+        if age < self.cooldown {
+            return Err(WithdrawRejection::CoolingDown {
+                remaining: self.cooldown - age,
+            }.into());
+        }
+        Ok(())
+    }
+
+    fn check_limits(&mut self, currency: &str, amount: f32) -> Result<()> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(WithdrawRejection::InvalidAmount(amount).into());
+        }
+        let limits = match self.limits.get(currency) {
+            Some(limits) => limits.clone(),
+            None => return Ok(()),
+        };
+        if let Some(limit) = limits.per_transaction {
+            if amount > limit {
+                return Err(WithdrawRejection::TransactionLimit { amount, limit }.into());
+            }
+        }
+        if let Some(limit) = limits.per_day {
+            let day_ago = SystemTime::now() - Duration::from_secs(DAY);
+            self.executed.retain(|&(time, _, _)| time > day_ago);
+            let total = amount
+                + self
+                    .executed
+                    .iter()
+                    .filter(|&(_, executed, _)| executed == currency)
+                    .map(|&(_, _, amount)| amount)
+                    .sum::<f32>();
+            if total > limit {
+                return Err(WithdrawRejection::DailyLimit { total, limit }.into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing;
+
+use error::{Error, ErrorType};
+use query::{Client, Result};
+use values::*;
+
+/// Status words of each state, checked in this order
+const CANCELLED: &[&str] = &["cancel", "cancelled", "canceled"];
+const FAILED: &[&str] = &["fail", "failed", "error", "rejected", "declined"];
+const PENDING: &[&str] = &["pending", "unconfirmed", "incomplete", "waiting"];
+const PROCESSING: &[&str] = &["process", "processing", "processed", "approved"];
+const COMPLETE: &[&str] = &["complete", "completed"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawalState {
+    Pending,
+    Processing,
+    Complete { tx_id: Option<String> },
+    Failed(String),
+    Cancelled,
+    /// Status text not recognized
+    Unknown(String),
+}
+
+impl WithdrawalState {
+    fn from_transaction(transaction: &Transaction) -> WithdrawalState {
+        WithdrawalState::from_status(&transaction.status, transaction.tx_id.clone())
+    }
+
+    /// State of a status text, matched by whole words with negative statuses first
+    ///
+    /// 'Incomplete' or 'Unconfirmed' are pending, never complete.
+    pub(crate) fn from_status(status: &str, tx_id: Option<String>) -> WithdrawalState {
+        let lower = status.to_lowercase();
+        let words: Vec<&str> = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let any = |names: &[&str]| words.iter().any(|word| names.contains(word));
+        if any(CANCELLED) {
+            WithdrawalState::Cancelled
+        } else if any(FAILED) {
+            WithdrawalState::Failed(status.to_string())
+        } else if any(PENDING) {
+            WithdrawalState::Pending
+        } else if any(PROCESSING) {
+            WithdrawalState::Processing
+        } else if any(COMPLETE) {
+            WithdrawalState::Complete { tx_id }
+        } else {
+            WithdrawalState::Unknown(status.to_string())
+        }
+    }
+
+    /// Whether the withdrawal can not change anymore
+    pub fn is_final(&self) -> bool {
+        matches!(
+            *self,
+            WithdrawalState::Complete { .. } | WithdrawalState::Failed(_) | WithdrawalState::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WithdrawalEvent {
+    pub id: String,
+    pub currency: String,
+    pub amount: f32,
+    /// None the first time the withdrawal shows up
+    pub previous: Option<WithdrawalState>,
+    pub state: WithdrawalState,
+}
+
+type Callback = Box<dyn FnMut(&WithdrawalEvent)>;
+
+/// Follows withdrawals by `Id::withdrawal_id` through `get_withdrawals`
+pub struct WithdrawalTracker {
+    watched: HashMap<String, Option<WithdrawalState>>,
+    callback: Option<Callback>,
+    count: u32,
+    poll_interval: Duration,
+}
+
+impl Default for WithdrawalTracker {
+    fn default() -> Self {
+        WithdrawalTracker::new()
+    }
+}
+
+impl WithdrawalTracker {
+    pub fn new() -> Self {
+        WithdrawalTracker {
+            watched: HashMap::new(),
+            callback: None,
+            count: 50,
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Withdrawals fetched on each poll (default: 50)
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Time between polls in `wait_for_completion` (default: 10s)
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Called for every state transition
+    pub fn on_event<F: FnMut(&WithdrawalEvent) + 'static>(mut self, callback: F) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Start following the withdrawal returned by `submit_withdraw`
+    pub fn watch(&mut self, id: &Id) {
+        self.watch_id(&id.withdrawal_id);
+    }
+
+    pub fn watch_id(&mut self, withdrawal_id: &str) {
+        self.watched.entry(withdrawal_id.to_string()).or_insert(None);
+    }
+
+    pub fn forget(&mut self, withdrawal_id: &str) {
+        self.watched.remove(withdrawal_id);
+    }
+
+    /// Last known state, None until the withdrawal showed up in `get_withdrawals`
+    pub fn state(&self, withdrawal_id: &str) -> Option<&WithdrawalState> {
+        self.watched
+            .get(withdrawal_id)
+            .and_then(|state| state.as_ref())
+    }
+
+    /// Poll withdrawals, returns the state transitions since the last poll
+    pub fn poll(&mut self, client: &Client) -> Result<Vec<WithdrawalEvent>> {
+        let mut events = Vec::new();
+        let open = self.watched.values().any(|state| match *state {
+            Some(ref state) => !state.is_final(),
+            None => true,
+        });
+        if !open {
+            return Ok(events);
+        }
+
+        for transaction in client.get_withdrawals(None, Some(self.count))? {
+            let previous = match self.watched.get_mut(&transaction.id) {
+                Some(previous) => previous,
+                None => continue,
+            };
+            let state = WithdrawalState::from_transaction(&transaction);
+            if previous.as_ref() == Some(&state) {
+                continue;
+            }
+            events.push(WithdrawalEvent {
+                id: transaction.id.clone(),
+                currency: transaction.currency.clone(),
+                amount: transaction.amount,
+                previous: previous.take(),
+                state: state.clone(),
+            });
+            *previous = Some(state);
+        }
+
+        if let Some(ref mut callback) = self.callback {
+            for event in &events {
+                callback(event);
+            }
+        }
+        Ok(events)
+    }
+
+    /// Poll until the withdrawal reaches a final state or the timeout passes
+    ///
+    /// Failed polls are retried until the timeout, the last error is part of the timeout error.
+    pub fn wait_for_completion(
+        &mut self,
+        client: &Client,
+        withdrawal_id: &str,
+        timeout: Duration,
+    ) -> Result<WithdrawalState> {
+        self.watch_id(withdrawal_id);
+        let started = Instant::now();
+        loop {
+            let last_error = self.poll(client).err();
+            if let Some(ref error) = last_error {
+                tracing::warn!(error = %error, "withdrawal poll failed, retrying");
+            }
+            if let Some(state) = self.state(withdrawal_id) {
+                if state.is_final() {
+                    return Ok(state.clone());
+                }
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                let mut message = format!(
+                    "Withdrawal {} not completed after {}s",
+                    withdrawal_id,
+                    elapsed.as_secs()
+                );
+                if let Some(error) = last_error {
+                    message = format!("{}, last poll failed: {}", message, error);
+                }
+                return Err(Error {
+                    error_type: ErrorType::Timeout,
+                    message,
+                });
+            }
+            thread::sleep(self.poll_interval.min(timeout - elapsed));
+        }
+    }
+}